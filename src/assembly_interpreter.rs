@@ -1,424 +1,1040 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Size of the emulated address space. The stack grows down from the top.
+const MEMORY_SIZE: u32 = 0x10000;
+/// Storage reserved by `DC`/`DS` directives is laid out upwards from here.
+const DATA_BASE: u32 = 0x1000;
+/// Code labels resolve to this base plus their instruction index, which keeps
+/// them apart from data addresses.
+const CODE_BASE: u32 = 0x0100_0000;
+/// Return address pushed before execution starts; an `RTS` to it halts.
+const HALT_ADDRESS: u32 = CODE_BASE - 2;
+/// Guards the tests against generated code that never terminates.
+const STEP_LIMIT: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Size {
+    Byte,
+    Word,
+    Long,
+}
+
+impl Size {
+    fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "B" => Some(Size::Byte),
+            "W" => Some(Size::Word),
+            "L" => Some(Size::Long),
+            _ => None,
+        }
+    }
+
+    fn bytes(self) -> u32 {
+        match self {
+            Size::Byte => 1,
+            Size::Word => 2,
+            Size::Long => 4,
+        }
+    }
+
+    fn mask(self) -> u32 {
+        match self {
+            Size::Byte => 0xFF,
+            Size::Word => 0xFFFF,
+            Size::Long => 0xFFFF_FFFF,
+        }
+    }
+
+    fn sign_bit(self) -> u32 {
+        (self.mask() >> 1) + 1
+    }
+
+    fn sign_extend(self, value: u32) -> u32 {
+        match self {
+            Size::Byte => value as u8 as i8 as i32 as u32,
+            Size::Word => value as u16 as i16 as i32 as u32,
+            Size::Long => value,
+        }
+    }
+}
+
+/// A number or a label; labels are resolved once the whole listing is loaded.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(i64),
+    Label(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Register {
+    Data(usize),
+    Address(usize),
+}
+
+/// The index register of an indexed operand, with the size of it that is
+/// used.
+#[derive(Debug, Clone, PartialEq)]
+struct Index {
+    register: Register,
+    size: Size,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    DataRegister(usize),
+    AddressRegister(usize),
+    Indirect(usize),
+    PostIncrement(usize),
+    PreDecrement(usize),
+    Displacement(i32, usize),
+    Indexed(i32, usize, Index),
+    PcRelative(Value),
+    PcIndexed(Value, Index),
+    Absolute(Value),
+    Immediate(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Location {
+    DataRegister(usize),
+    AddressRegister(usize),
+    Memory(u32),
+    Immediate(u32),
+}
+
+#[derive(Debug, Clone)]
+struct Instruction {
+    opcode: String,
+    size: Option<Size>,
+    operands: Vec<Operand>,
+}
+
+#[derive(Debug, Default)]
+struct Flags {
+    n: bool,
+    z: bool,
+    v: bool,
+    c: bool,
+}
+
 #[derive(Debug)]
 pub struct AssemblyInterpreter {
-    d0: Option<isize>,
-    d1: Option<isize>,
-    stack: Vec<isize>,
-    output: Option<isize>,
+    d: [u32; 8],
+    a: [u32; 8],
+    flags: Flags,
+    pc: usize,
+    halted: bool,
+    memory: Vec<u8>,
+    program: Vec<Instruction>,
+    labels: HashMap<String, u32>,
 }
 
+#[derive(Debug)]
 pub enum AssemblyInterpreterError {
-    Unexpected,
+    Syntax(String),
+    Unexpected(String),
+    UndefinedLabel(String),
+    AddressError(u32),
+    DivideByZero,
+    /// CHK found a register outside the range it was given.
+    ChkException(i32),
+    StepLimit,
+}
+
+impl fmt::Display for AssemblyInterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssemblyInterpreterError::Syntax(line) => write!(f, "Cannot parse {:?}", line),
+            AssemblyInterpreterError::Unexpected(opcode) => {
+                write!(f, "Instruction {} not implemented", opcode)
+            }
+            AssemblyInterpreterError::UndefinedLabel(label) => {
+                write!(f, "Undefined label {}", label)
+            }
+            AssemblyInterpreterError::AddressError(address) => {
+                write!(f, "Address error at ${:X}", address)
+            }
+            AssemblyInterpreterError::DivideByZero => write!(f, "Divide by zero"),
+            AssemblyInterpreterError::ChkException(value) => {
+                write!(f, "CHK exception: {} out of bounds", value)
+            }
+            AssemblyInterpreterError::StepLimit => write!(f, "Step limit exceeded"),
+        }
+    }
+}
+
+use AssemblyInterpreterError::*;
+
+/// Parses a number in any of the forms the compiler accepts: decimal,
+/// `$`/`0x` hex, `%`/`0b` binary, `@`/`0o` octal, with optional `_` digit
+/// separators and a leading `-`.
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+
+    let (radix, digits) = if let Some(rest) = text.strip_prefix('$') {
+        (16, rest)
+    } else if let Some(rest) = text.strip_prefix('%') {
+        (2, rest)
+    } else if let Some(rest) = text.strip_prefix('@') {
+        (8, rest)
+    } else if let Some(rest) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        (16, rest)
+    } else if let Some(rest) = text.strip_prefix("0b").or(text.strip_prefix("0B")) {
+        (2, rest)
+    } else if let Some(rest) = text.strip_prefix("0o").or(text.strip_prefix("0O")) {
+        (8, rest)
+    } else {
+        (10, text)
+    };
+
+    let digits = digits.replace('_', "");
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let value = i64::from_str_radix(&digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some(num) = parse_number(text) {
+        return Some(Value::Number(num));
+    }
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => (),
+        _ => return None,
+    }
+    if chars.all(|c| c.is_alphanumeric() || c == '_') {
+        Some(Value::Label(text.to_string()))
+    } else {
+        None
+    }
+}
+
+fn parse_register(text: &str) -> Option<Register> {
+    if text == "SP" {
+        return Some(Register::Address(7));
+    }
+    let mut chars = text.chars();
+    let kind = chars.next()?;
+    let n = chars.as_str().parse::<usize>().ok().filter(|n| *n < 8)?;
+    match kind {
+        'D' => Some(Register::Data(n)),
+        'A' => Some(Register::Address(n)),
+        _ => None,
+    }
+}
+
+fn parse_address_register(text: &str) -> Option<usize> {
+    match parse_register(text)? {
+        Register::Address(n) => Some(n),
+        Register::Data(_) => None,
+    }
+}
+
+/// Parses an index register such as `D0`, `D0.W` or `A1.L`.
+fn parse_index(text: &str) -> Option<Index> {
+    let (register, size) = match text.split_once('.') {
+        Some((register, size)) => (parse_register(register)?, Size::from_suffix(size)?),
+        None => (parse_register(text)?, Size::Word),
+    };
+    Some(Index { register, size })
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    if let Some(value) = text.strip_prefix('#') {
+        return Some(Operand::Immediate(parse_value(value)?));
+    }
+    if let Some(register) = parse_register(text) {
+        return Some(match register {
+            Register::Data(n) => Operand::DataRegister(n),
+            Register::Address(n) => Operand::AddressRegister(n),
+        });
+    }
+    if let Some(inner) = text.strip_prefix("-(").and_then(|t| t.strip_suffix(')')) {
+        return Some(Operand::PreDecrement(parse_address_register(inner)?));
+    }
+    if let Some(inner) = text.strip_prefix('(').and_then(|t| t.strip_suffix(")+")) {
+        return Some(Operand::PostIncrement(parse_address_register(inner)?));
+    }
+    if let Some(open) = text.find('(') {
+        let outer = &text[..open];
+        let inner = text[open + 1..].strip_suffix(')')?;
+        let (base, index) = match inner.split_once(',') {
+            Some((base, index)) => (base, Some(parse_index(index)?)),
+            None => (inner, None),
+        };
+        if base == "PC" {
+            let value = parse_value(outer)?;
+            return Some(match index {
+                Some(index) => Operand::PcIndexed(value, index),
+                None => Operand::PcRelative(value),
+            });
+        }
+        let base = parse_address_register(base)?;
+        let displacement = if outer.is_empty() {
+            0
+        } else {
+            parse_number(outer)? as i32
+        };
+        return Some(match index {
+            Some(index) => Operand::Indexed(displacement, base, index),
+            None if outer.is_empty() => Operand::Indirect(base),
+            None => Operand::Displacement(displacement, base),
+        });
+    }
+    Some(Operand::Absolute(parse_value(text)?))
+}
+
+/// Splits operands at the commas outside parentheses and quoted strings.
+fn split_operands(text: &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            _ if quoted => (),
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        operands.push(text[start..].trim());
+    }
+    operands
 }
 
 impl AssemblyInterpreter {
     fn new() -> Self {
         Self {
-            d0: None,
-            d1: None,
-            stack: Vec::new(),
-            output: None,
+            d: [0; 8],
+            a: [0; 8],
+            flags: Flags::default(),
+            pc: 0,
+            halted: false,
+            memory: vec![0; MEMORY_SIZE as usize],
+            program: Vec::new(),
+            labels: HashMap::new(),
         }
     }
 
+    /// Loads an assembly listing and runs it from the first instruction until
+    /// it falls off the end or returns from the outermost subroutine.
     fn eval(&mut self, input: String) -> Result<(), AssemblyInterpreterError> {
-        let input = input.chars().collect::<Vec<char>>();
-        let input: String = input[1..input.len()].iter().collect();
-        let instructions = input
-            .split("\n")
-            .map(|s| String::from(s))
-            .collect::<Vec<String>>();
+        self.load(&input)?;
+        self.a[7] = MEMORY_SIZE;
+        self.push(Size::Long, HALT_ADDRESS)?;
 
-        println!("{:?}", instructions);
-
-        for instruction in instructions {
-            self.process_instruction(instruction);
+        let mut steps = 0;
+        while !self.halted && self.pc < self.program.len() {
+            steps += 1;
+            if steps > STEP_LIMIT {
+                return Err(StepLimit);
+            }
+            let instruction = self.program[self.pc].clone();
+            self.pc += 1;
+            self.process_instruction(&instruction)?;
         }
 
         Ok(())
     }
 
-    fn process_instruction(&mut self, instruction: String) -> Result<(), AssemblyInterpreterError> {
-        println!("{:?}", self);
-        let mut opcode = String::new();
-        let mut instruction: Vec<char> = instruction.chars().collect();
-        let mut index = 0;
-        for i in 0..instruction.len() {
-            if instruction[i] == ' ' {
-                index = i + 1;
-                break;
+    fn load(&mut self, input: &str) -> Result<(), AssemblyInterpreterError> {
+        let mut data = DATA_BASE;
+        let mut pending_labels = Vec::new();
+        let mut fixups = Vec::new();
+        for line in input.lines() {
+            let mut line = line.trim();
+            if line.is_empty() || line.starts_with('*') {
+                continue;
             }
 
-            opcode.push(instruction[i]);
-        }
+            if let Some((label, rest)) = line.split_once(':') {
+                line = rest.trim();
+                if line.is_empty() {
+                    pending_labels.push(label.trim().to_string());
+                    continue;
+                }
+                // Code and data live in separate spaces here, so labels left
+                // over from the end of the code must not follow a labelled
+                // directive into the data.
+                if line.starts_with("DC") || line.starts_with("DS") {
+                    for label in pending_labels.drain(..) {
+                        self.labels
+                            .insert(label, CODE_BASE + self.program.len() as u32);
+                    }
+                }
+                pending_labels.push(label.trim().to_string());
+            }
+
+            let (mnemonic, rands) = match line.split_once(char::is_whitespace) {
+                Some((mnemonic, rands)) => (mnemonic, rands.trim()),
+                None => (line, ""),
+            };
+            let (opcode, size) = match mnemonic.split_once('.') {
+                Some((opcode, suffix)) => (
+                    opcode,
+                    Some(Size::from_suffix(suffix).ok_or_else(|| Syntax(line.to_string()))?),
+                ),
+                None => (mnemonic, None),
+            };
+
+            if opcode == "DC" || opcode == "DS" {
+                let size = size.unwrap_or(Size::Word);
+                if size != Size::Byte && !data.is_multiple_of(2) {
+                    data += 1;
+                }
+                for label in pending_labels.drain(..) {
+                    self.labels.insert(label, data);
+                }
+                data = self.directive(opcode, size, rands, data, &mut fixups)?;
+                continue;
+            }
 
-        let mut rands = &instruction[index..instruction.len()];
+            for label in pending_labels.drain(..) {
+                self.labels
+                    .insert(label, CODE_BASE + self.program.len() as u32);
+            }
+            let operands = split_operands(rands)
+                .into_iter()
+                .map(|rand| parse_operand(rand).ok_or_else(|| Syntax(line.to_string())))
+                .collect::<Result<Vec<Operand>, AssemblyInterpreterError>>()?;
+            self.program.push(Instruction {
+                opcode: opcode.to_string(),
+                size,
+                operands,
+            });
+        }
 
-        match &opcode[..] {
-            "MOVE" => self.move_op(rands),
-            "ADD" => self.add_op(rands),
-            "SUB" => self.sub_op(rands),
-            "NEG" => self.sub_op(rands),
-            "MULS" => self.muls_op(rands),
-            "DIVS" => self.divs_op(rands),
-            _ => panic!("Not implemented!"),
+        for label in pending_labels {
+            self.labels
+                .insert(label, CODE_BASE + self.program.len() as u32);
+        }
+        for (address, size, label) in fixups {
+            let value = self.resolve(&Value::Label(label))?;
+            self.write_memory(address, size, value)?;
         }
 
         Ok(())
     }
 
-    fn divs_op(&mut self, rands: &[char]) {
-        use std::convert::TryInto;
+    /// Lays out a `DC` (define constants) or `DS` (define storage) directive
+    /// at `address`, returning the address just past it. Labels among the
+    /// values are added to `fixups`, to be written once all are known.
+    fn directive(
+        &mut self,
+        opcode: &str,
+        size: Size,
+        rands: &str,
+        mut address: u32,
+        fixups: &mut Vec<(u32, Size, String)>,
+    ) -> Result<u32, AssemblyInterpreterError> {
+        if opcode == "DS" {
+            let count = parse_number(rands).ok_or_else(|| Syntax(rands.to_string()))?;
+            return Ok(address + size.bytes() * count as u32);
+        }
 
-        let mut temp: isize = 0;
-        let mut i = 0;
-        let mut arg_pos = 0;
-        for _ in 0..rands.len() {
-            if i >= rands.len() {
-                break;
-            }
-            match rands[i] {
-                '(' => match &rands[i + 1..i + 5] {
-                    &['S', 'P', ')', '+'] => {
-                        temp = self.stack.pop().unwrap();
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                '#' => {
-                    temp = (rands[i + 1].to_digit(10).unwrap()).try_into().unwrap();
-                    i += 2;
-                }
-                ',' => {
-                    arg_pos += 1;
-                    i += 1;
-                }
-                '-' => match &rands[i + 1..i + 5] {
-                    &['(', 'S', 'P', ')'] => {
-                        self.stack.push(temp);
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                'D' => match arg_pos {
-                    0 => {
-                        match rands[i + 1] {
-                            '0' => temp = self.d0.unwrap(),
-                            '1' => temp = self.d1.unwrap(),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
+        for rand in split_operands(rands) {
+            // A quoted string stands for one value per character, with `''`
+            // for a quote.
+            let values = match rand.strip_prefix('\'').and_then(|r| r.strip_suffix('\'')) {
+                Some(text) => text.replace("''", "'").bytes().map(u32::from).collect(),
+                None => match parse_value(rand).ok_or_else(|| Syntax(rand.to_string()))? {
+                    Value::Number(num) => vec![num as u32],
+                    Value::Label(label) => {
+                        fixups.push((address, size, label));
+                        vec![0]
                     }
-                    1 => {
-                        match rands[i + 1] {
-                            '0' => self.d0 = Some(temp / self.d0.unwrap()),
-                            '1' => self.d1 = Some(temp / self.d0.unwrap()),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    x => panic!("Invalid argument position: {}", x),
                 },
-                x => panic!("Not implemented! {}", x),
+            };
+            for value in values {
+                self.write_memory(address, size, value)?;
+                address += size.bytes();
             }
         }
+        Ok(address)
+    }
+
+    fn resolve(&self, value: &Value) -> Result<u32, AssemblyInterpreterError> {
+        match value {
+            Value::Number(num) => Ok(*num as u32),
+            Value::Label(label) => self
+                .labels
+                .get(label)
+                .copied()
+                .ok_or_else(|| UndefinedLabel(label.clone())),
+        }
+    }
+
+    fn check_address(&self, address: u32, size: Size) -> Result<usize, AssemblyInterpreterError> {
+        if address + size.bytes() > MEMORY_SIZE
+            || (size != Size::Byte && !address.is_multiple_of(2))
+        {
+            return Err(AddressError(address));
+        }
+        Ok(address as usize)
+    }
+
+    fn read_memory(&self, address: u32, size: Size) -> Result<u32, AssemblyInterpreterError> {
+        let address = self.check_address(address, size)?;
+        let bytes = &self.memory[address..address + size.bytes() as usize];
+        Ok(bytes
+            .iter()
+            .fold(0, |value, byte| (value << 8) | u32::from(*byte)))
+    }
+
+    fn write_memory(
+        &mut self,
+        address: u32,
+        size: Size,
+        value: u32,
+    ) -> Result<(), AssemblyInterpreterError> {
+        let address = self.check_address(address, size)?;
+        let count = size.bytes() as usize;
+        for i in 0..count {
+            self.memory[address + i] = (value >> (8 * (count - 1 - i))) as u8;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, size: Size, value: u32) -> Result<(), AssemblyInterpreterError> {
+        self.a[7] = self.a[7].wrapping_sub(size.bytes().max(2));
+        self.write_memory(self.a[7], size, value)
     }
 
-    fn muls_op(&mut self, rands: &[char]) {
-        use std::convert::TryInto;
+    fn pop(&mut self, size: Size) -> Result<u32, AssemblyInterpreterError> {
+        let value = self.read_memory(self.a[7], size)?;
+        self.a[7] = self.a[7].wrapping_add(size.bytes().max(2));
+        Ok(value)
+    }
+
+    fn index_value(&self, index: &Index) -> u32 {
+        let value = match index.register {
+            Register::Data(n) => self.d[n],
+            Register::Address(n) => self.a[n],
+        };
+        index.size.sign_extend(value & index.size.mask())
+    }
 
-        let mut temp: isize = 0;
-        let mut i = 0;
-        let mut arg_pos = 0;
-        for _ in 0..rands.len() {
-            if i >= rands.len() {
-                break;
+    /// Computes the address an operand refers to, without any side effects.
+    fn effective_address(&self, operand: &Operand) -> Result<u32, AssemblyInterpreterError> {
+        Ok(match operand {
+            Operand::Indirect(n) => self.a[*n],
+            Operand::Displacement(d, n) => self.a[*n].wrapping_add(*d as u32),
+            Operand::Indexed(d, n, index) => self.a[*n]
+                .wrapping_add(*d as u32)
+                .wrapping_add(self.index_value(index)),
+            Operand::PcRelative(value) | Operand::Absolute(value) => self.resolve(value)?,
+            Operand::PcIndexed(value, index) => {
+                self.resolve(value)?.wrapping_add(self.index_value(index))
             }
-            match rands[i] {
-                '(' => match &rands[i + 1..i + 5] {
-                    &['S', 'P', ')', '+'] => {
-                        temp = self.stack.pop().unwrap();
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                '#' => {
-                    temp = (rands[i + 1].to_digit(10).unwrap()).try_into().unwrap();
-                    i += 2;
-                }
-                ',' => {
-                    arg_pos += 1;
-                    i += 1;
-                }
-                '-' => match &rands[i + 1..i + 5] {
-                    &['(', 'S', 'P', ')'] => {
-                        self.stack.push(temp);
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                'D' => match arg_pos {
-                    0 => {
-                        match rands[i + 1] {
-                            '0' => temp = self.d0.unwrap(),
-                            '1' => temp = self.d1.unwrap(),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    1 => {
-                        match rands[i + 1] {
-                            '0' => self.d0 = Some(temp * self.d0.unwrap()),
-                            '1' => self.d1 = Some(temp * self.d0.unwrap()),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    x => panic!("Invalid argument position: {}", x),
-                },
-                x => panic!("Not implemented! {}", x),
+            x => return Err(Syntax(format!("{:?}", x))),
+        })
+    }
+
+    /// Resolves an operand to the location it reads or writes, applying any
+    /// post-increment or pre-decrement.
+    fn location(
+        &mut self,
+        operand: &Operand,
+        size: Size,
+    ) -> Result<Location, AssemblyInterpreterError> {
+        let step = if size == Size::Byte { 1 } else { size.bytes() };
+        Ok(match operand {
+            Operand::DataRegister(n) => Location::DataRegister(*n),
+            Operand::AddressRegister(n) => Location::AddressRegister(*n),
+            Operand::Immediate(value) => Location::Immediate(self.resolve(value)?),
+            Operand::PostIncrement(n) => {
+                let address = self.a[*n];
+                self.a[*n] = address.wrapping_add(if *n == 7 { step.max(2) } else { step });
+                Location::Memory(address)
             }
+            Operand::PreDecrement(n) => {
+                self.a[*n] = self.a[*n].wrapping_sub(if *n == 7 { step.max(2) } else { step });
+                Location::Memory(self.a[*n])
+            }
+            x => Location::Memory(self.effective_address(x)?),
+        })
+    }
+
+    fn get(&self, location: Location, size: Size) -> Result<u32, AssemblyInterpreterError> {
+        Ok(match location {
+            Location::DataRegister(n) => self.d[n] & size.mask(),
+            Location::AddressRegister(n) => self.a[n] & size.mask(),
+            Location::Memory(address) => self.read_memory(address, size)?,
+            Location::Immediate(value) => value & size.mask(),
+        })
+    }
+
+    fn set(
+        &mut self,
+        location: Location,
+        size: Size,
+        value: u32,
+    ) -> Result<(), AssemblyInterpreterError> {
+        let value = value & size.mask();
+        match location {
+            Location::DataRegister(n) => self.d[n] = (self.d[n] & !size.mask()) | value,
+            Location::AddressRegister(n) => self.a[n] = size.sign_extend(value),
+            Location::Memory(address) => self.write_memory(address, size, value)?,
+            Location::Immediate(_) => return Err(Syntax(String::from("write to immediate"))),
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, operand: &Operand, size: Size) -> Result<u32, AssemblyInterpreterError> {
+        let location = self.location(operand, size)?;
+        self.get(location, size)
+    }
+
+    fn set_logic_flags(&mut self, value: u32, size: Size) {
+        let value = value & size.mask();
+        self.flags.n = value & size.sign_bit() != 0;
+        self.flags.z = value == 0;
+        self.flags.v = false;
+        self.flags.c = false;
+    }
+
+    fn add_with_flags(&mut self, dst: u32, src: u32, size: Size) -> u32 {
+        let result = dst.wrapping_add(src) & size.mask();
+        let sign = size.sign_bit();
+        self.flags.n = result & sign != 0;
+        self.flags.z = result == 0;
+        self.flags.v = (dst & sign) == (src & sign) && (result & sign) != (dst & sign);
+        self.flags.c =
+            u64::from(dst & size.mask()) + u64::from(src & size.mask()) > u64::from(size.mask());
+        result
+    }
+
+    fn sub_with_flags(&mut self, dst: u32, src: u32, size: Size) -> u32 {
+        let result = dst.wrapping_sub(src) & size.mask();
+        let sign = size.sign_bit();
+        self.flags.n = result & sign != 0;
+        self.flags.z = result == 0;
+        self.flags.v = (dst & sign) != (src & sign) && (result & sign) != (dst & sign);
+        self.flags.c = (src & size.mask()) > (dst & size.mask());
+        result
+    }
+
+    fn condition(&self, cc: &str) -> Option<bool> {
+        let f = &self.flags;
+        Some(match cc {
+            "T" | "RA" => true,
+            "F" => false,
+            "HI" => !f.c && !f.z,
+            "LS" => f.c || f.z,
+            "CC" | "HS" => !f.c,
+            "CS" | "LO" => f.c,
+            "NE" => !f.z,
+            "EQ" => f.z,
+            "VC" => !f.v,
+            "VS" => f.v,
+            "PL" => !f.n,
+            "MI" => f.n,
+            "GE" => f.n == f.v,
+            "LT" => f.n != f.v,
+            "GT" => !f.z && f.n == f.v,
+            "LE" => f.z || f.n != f.v,
+            _ => return None,
+        })
+    }
+
+    fn jump(&mut self, address: u32) -> Result<(), AssemblyInterpreterError> {
+        if address == HALT_ADDRESS {
+            self.halted = true;
+            return Ok(());
+        }
+        if address < CODE_BASE || address > CODE_BASE + self.program.len() as u32 {
+            return Err(AddressError(address));
         }
+        self.pc = address.wrapping_sub(CODE_BASE) as usize;
+        Ok(())
+    }
+
+    fn operand(
+        &self,
+        instruction: &Instruction,
+        index: usize,
+    ) -> Result<Operand, AssemblyInterpreterError> {
+        instruction
+            .operands
+            .get(index)
+            .cloned()
+            .ok_or_else(|| Syntax(instruction.opcode.clone()))
     }
 
-    fn neg_op(&mut self, rands: &[char]) {
-        use std::convert::TryInto;
+    fn address_register(
+        &self,
+        instruction: &Instruction,
+    ) -> Result<usize, AssemblyInterpreterError> {
+        match self.operand(instruction, 0)? {
+            Operand::AddressRegister(n) => Ok(n),
+            x => Err(Syntax(format!("{} on {:?}", instruction.opcode, x))),
+        }
+    }
 
-        let mut temp: isize = 0;
-        let mut i = 0;
-        let mut arg_pos = 0;
-        for _ in 0..rands.len() {
-            if i >= rands.len() {
-                break;
+    fn process_instruction(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<(), AssemblyInterpreterError> {
+        let size = instruction.size.unwrap_or(Size::Word);
+        let opcode = &instruction.opcode[..];
+        match opcode {
+            "MOVE" | "MOVEA" => self.move_op(instruction, size),
+            "LEA" => {
+                let address = self.effective_address(&self.operand(instruction, 0)?)?;
+                match self.operand(instruction, 1)? {
+                    Operand::AddressRegister(n) => self.a[n] = address,
+                    x => return Err(Syntax(format!("LEA to {:?}", x))),
+                }
+                Ok(())
             }
-            match rands[i] {
-                '(' => match &rands[i + 1..i + 5] {
-                    &['S', 'P', ')', '+'] => {
-                        temp = self.stack.pop().unwrap();
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                '#' => {
-                    temp = (rands[i + 1].to_digit(10).unwrap()).try_into().unwrap();
-                    i += 2;
+            "CLR" => {
+                let location = self.location(&self.operand(instruction, 0)?, size)?;
+                self.set(location, size, 0)?;
+                self.set_logic_flags(0, size);
+                Ok(())
+            }
+            "TST" => {
+                let value = self.read(&self.operand(instruction, 0)?, size)?;
+                self.set_logic_flags(value, size);
+                Ok(())
+            }
+            "ADD" | "SUB" | "CMP" | "ADDA" | "SUBA" | "CMPA" | "ADDQ" | "SUBQ" => {
+                self.arithmetic_op(instruction, size)
+            }
+            "NEG" | "NOT" | "EXT" => self.unary_op(instruction, size),
+            "ASL" | "ASR" => self.shift_op(instruction, size),
+            "SWAP" => match self.operand(instruction, 0)? {
+                Operand::DataRegister(n) => {
+                    self.d[n] = self.d[n].rotate_left(16);
+                    self.set_logic_flags(self.d[n], Size::Long);
+                    Ok(())
                 }
-                ',' => {
-                    arg_pos += 1;
-                    i += 1;
+                x => Err(Syntax(format!("SWAP on {:?}", x))),
+            },
+            "AND" | "OR" | "EOR" => self.logic_op(instruction, size),
+            "MULS" | "MULU" => self.multiply_op(instruction, size),
+            "DIVS" => self.divs_op(instruction, size),
+            "CHK" => {
+                // Traps unless 0 <= Dn <= the bound, setting N if Dn < 0.
+                let bound = size.sign_extend(self.read(&self.operand(instruction, 0)?, size)?);
+                let value = size.sign_extend(self.read(&self.operand(instruction, 1)?, size)?);
+                if (value as i32) < 0 || value as i32 > bound as i32 {
+                    self.flags.n = (value as i32) < 0;
+                    return Err(ChkException(value as i32));
                 }
-                '-' => match &rands[i + 1..i + 5] {
-                    &['(', 'S', 'P', ')'] => {
-                        self.stack.push(temp);
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                'D' => match arg_pos {
-                    0 => {
-                        match rands[i + 1] {
-                            '0' => self.d0 = Some(-self.d0.unwrap()),
-                            '1' => self.d1 = Some(-self.d1.unwrap()),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    1 => {
-                        match rands[i + 1] {
-                            '0' => self.d0 = Some(temp - self.d0.unwrap()),
-                            '1' => self.d1 = Some(temp - self.d0.unwrap()),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    x => panic!("Invalid argument position: {}", x),
-                },
-                x => panic!("Not implemented! {}", x),
+                Ok(())
+            }
+            "JMP" => {
+                let target = self.effective_address(&self.operand(instruction, 0)?)?;
+                self.jump(target)
+            }
+            "BSR" => {
+                let target = self.effective_address(&self.operand(instruction, 0)?)?;
+                self.push(Size::Long, CODE_BASE + self.pc as u32)?;
+                self.jump(target)
+            }
+            "RTS" => {
+                let address = self.pop(Size::Long)?;
+                self.jump(address)
+            }
+            "PEA" => {
+                let address = self.effective_address(&self.operand(instruction, 0)?)?;
+                self.push(Size::Long, address)
+            }
+            "LINK" => {
+                let n = self.address_register(instruction)?;
+                let displacement = self.read(&self.operand(instruction, 1)?, Size::Word)?;
+                self.push(Size::Long, self.a[n])?;
+                self.a[n] = self.a[7];
+                self.a[7] = self.a[7].wrapping_add(Size::Word.sign_extend(displacement));
+                Ok(())
+            }
+            "UNLK" => {
+                let n = self.address_register(instruction)?;
+                self.a[7] = self.a[n];
+                self.a[n] = self.pop(Size::Long)?;
+                Ok(())
             }
+            _ => self.conditional_op(instruction),
         }
     }
 
-    fn sub_op(&mut self, rands: &[char]) {
-        use std::convert::TryInto;
-
-        let mut temp: isize = 0;
-        let mut i = 0;
-        let mut arg_pos = 0;
-        for _ in 0..rands.len() {
-            if i >= rands.len() {
-                break;
+    /// Handles the instructions whose mnemonic embeds a condition code.
+    fn conditional_op(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<(), AssemblyInterpreterError> {
+        let opcode = &instruction.opcode[..];
+        let unknown = || Unexpected(opcode.to_string());
+        if let Some(cc) = opcode.strip_prefix("DB") {
+            // DBcc falls through when the condition holds; otherwise it
+            // decrements the low word of the counter and loops until -1.
+            // DBRA is the assembler's name for DBF.
+            let cc = if cc == "RA" { "F" } else { cc };
+            if self.condition(cc).ok_or_else(unknown)? {
+                return Ok(());
             }
-            match rands[i] {
-                '(' => match &rands[i + 1..i + 5] {
-                    &['S', 'P', ')', '+'] => {
-                        temp = self.stack.pop().unwrap();
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                '#' => {
-                    temp = (rands[i + 1].to_digit(10).unwrap()).try_into().unwrap();
-                    i += 2;
-                }
-                ',' => {
-                    arg_pos += 1;
-                    i += 1;
-                }
-                '-' => match &rands[i + 1..i + 5] {
-                    &['(', 'S', 'P', ')'] => {
-                        self.stack.push(temp);
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                'D' => match arg_pos {
-                    0 => {
-                        match rands[i + 1] {
-                            '0' => temp = self.d0.unwrap(),
-                            '1' => temp = self.d1.unwrap(),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    1 => {
-                        match rands[i + 1] {
-                            '0' => self.d0 = Some(temp - self.d0.unwrap()),
-                            '1' => self.d1 = Some(temp - self.d0.unwrap()),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    x => panic!("Invalid argument position: {}", x),
-                },
-                x => panic!("Not implemented! {}", x),
+            let n = match self.operand(instruction, 0)? {
+                Operand::DataRegister(n) => n,
+                x => return Err(Syntax(format!("DBcc on {:?}", x))),
+            };
+            let count = (self.d[n] as u16).wrapping_sub(1);
+            self.d[n] = (self.d[n] & 0xFFFF_0000) | u32::from(count);
+            if count != 0xFFFF {
+                let target = self.effective_address(&self.operand(instruction, 1)?)?;
+                self.jump(target)?;
             }
+            Ok(())
+        } else if let Some(cc) = opcode.strip_prefix('B') {
+            let taken = self.condition(cc).ok_or_else(unknown)?;
+            if taken {
+                let target = self.effective_address(&self.operand(instruction, 0)?)?;
+                self.jump(target)?;
+            }
+            Ok(())
+        } else if let Some(cc) = opcode.strip_prefix('S') {
+            let value = if self.condition(cc).ok_or_else(unknown)? {
+                0xFF
+            } else {
+                0
+            };
+            let location = self.location(&self.operand(instruction, 0)?, Size::Byte)?;
+            self.set(location, Size::Byte, value)
+        } else {
+            Err(unknown())
+        }
+    }
+
+    fn move_op(
+        &mut self,
+        instruction: &Instruction,
+        size: Size,
+    ) -> Result<(), AssemblyInterpreterError> {
+        let value = self.read(&self.operand(instruction, 0)?, size)?;
+        let dst = self.location(&self.operand(instruction, 1)?, size)?;
+        self.set(dst, size, value)?;
+        if let Location::AddressRegister(_) = dst {
+            return Ok(());
         }
+        self.set_logic_flags(value, size);
+        Ok(())
     }
 
-    fn add_op(&mut self, rands: &[char]) {
-        use std::convert::TryInto;
+    fn arithmetic_op(
+        &mut self,
+        instruction: &Instruction,
+        size: Size,
+    ) -> Result<(), AssemblyInterpreterError> {
+        let src = self.read(&self.operand(instruction, 0)?, size)?;
+        let dst = self.location(&self.operand(instruction, 1)?, size)?;
+        let opcode = instruction.opcode.trim_end_matches(['A', 'Q']);
 
-        let mut temp: isize = 0;
-        let mut i = 0;
-        let mut arg_pos = 0;
-        for _ in 0..rands.len() {
-            if i >= rands.len() {
-                break;
-            }
-            match rands[i] {
-                '(' => match &rands[i + 1..i + 5] {
-                    &['S', 'P', ')', '+'] => {
-                        temp = self.stack.pop().unwrap();
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                '#' => {
-                    temp = (rands[i + 1].to_digit(10).unwrap()).try_into().unwrap();
-                    i += 2;
+        if let Location::AddressRegister(n) = dst {
+            // Address register arithmetic always works on the full register,
+            // and only CMPA touches the flags.
+            let src = size.sign_extend(src);
+            match opcode {
+                "ADD" => self.a[n] = self.a[n].wrapping_add(src),
+                "SUB" => self.a[n] = self.a[n].wrapping_sub(src),
+                _ => {
+                    self.sub_with_flags(self.a[n], src, Size::Long);
                 }
-                ',' => {
-                    arg_pos += 1;
-                    i += 1;
-                }
-                '-' => match &rands[i + 1..i + 5] {
-                    &['(', 'S', 'P', ')'] => {
-                        self.stack.push(temp);
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                'D' => match arg_pos {
-                    0 => {
-                        match rands[i + 1] {
-                            '0' => temp = self.d0.unwrap(),
-                            '1' => temp = self.d1.unwrap(),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    1 => {
-                        match rands[i + 1] {
-                            '0' => self.d0 = Some(temp + self.d0.unwrap()),
-                            '1' => self.d1 = Some(temp + self.d0.unwrap()),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    x => panic!("Invalid argument position: {}", x),
-                },
-                x => panic!("Not implemented! {}", x),
             }
+            return Ok(());
         }
-    }
 
-    fn move_op(&mut self, rands: &[char]) {
-        use std::convert::TryInto;
+        let value = self.get(dst, size)?;
+        match opcode {
+            "ADD" => {
+                let result = self.add_with_flags(value, src, size);
+                self.set(dst, size, result)
+            }
+            "SUB" => {
+                let result = self.sub_with_flags(value, src, size);
+                self.set(dst, size, result)
+            }
+            _ => {
+                self.sub_with_flags(value, src, size);
+                Ok(())
+            }
+        }
+    }
 
-        let mut temp: isize = 0;
-        let mut i = 0;
-        let mut arg_pos = 0;
-        for _ in 0..rands.len() {
-            if i >= rands.len() {
-                break;
+    fn unary_op(
+        &mut self,
+        instruction: &Instruction,
+        size: Size,
+    ) -> Result<(), AssemblyInterpreterError> {
+        let location = self.location(&self.operand(instruction, 0)?, size)?;
+        match &instruction.opcode[..] {
+            "NEG" => {
+                let value = self.get(location, size)?;
+                let result = self.sub_with_flags(0, value, size);
+                self.set(location, size, result)
             }
-            match rands[i] {
-                '(' => match &rands[i + 1..i + 5] {
-                    &['S', 'P', ')', '+'] => {
-                        temp = self.stack.pop().unwrap();
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                '#' => {
-                    temp = (rands[i + 1].to_digit(10).unwrap()).try_into().unwrap();
-                    i += 2;
-                }
-                ',' => {
-                    arg_pos += 1;
-                    i += 1;
-                }
-                '-' => match &rands[i + 1..i + 5] {
-                    &['(', 'S', 'P', ')'] => {
-                        self.stack.push(temp);
-                        i += 5;
-                    }
-                    x => panic!("Unexpected stack operation: {:?}", x),
-                },
-                'D' => match arg_pos {
-                    0 => {
-                        match rands[i + 1] {
-                            '0' => temp = self.d0.unwrap(),
-                            '1' => temp = self.d1.unwrap(),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    1 => {
-                        match rands[i + 1] {
-                            '0' => self.d0 = Some(temp),
-                            '1' => self.d1 = Some(temp),
-                            n => panic!("Unknown register D{}", n),
-                        }
-                        i += 2;
-                    }
-                    x => panic!("Invalid argument position: {}", x),
-                },
-                x => {
-                    println!("{:?}", rands);
-                    panic!("Not implemented! {}", x)
-                }
+            "NOT" => {
+                let result = !self.get(location, size)?;
+                self.set_logic_flags(result, size);
+                self.set(location, size, result)
             }
+            _ => {
+                // EXT.W extends a byte to a word and EXT.L a word to a long.
+                let from = if size == Size::Long {
+                    Size::Word
+                } else {
+                    Size::Byte
+                };
+                let result = from.sign_extend(self.get(location, from)?);
+                self.set_logic_flags(result, size);
+                self.set(location, size, result)
+            }
+        }
+    }
+
+    /// Arithmetic shift by an immediate count or one in a register, taken
+    /// modulo 64. C is the last bit shifted out, and ASL sets V if the sign
+    /// changed on the way.
+    fn shift_op(
+        &mut self,
+        instruction: &Instruction,
+        size: Size,
+    ) -> Result<(), AssemblyInterpreterError> {
+        let count = self.read(&self.operand(instruction, 0)?, Size::Long)? % 64;
+        let dst = self.location(&self.operand(instruction, 1)?, size)?;
+        let value = i128::from(size.sign_extend(self.get(dst, size)?) as i32);
+        let bits = 8 * size.bytes();
+        let shifted = if instruction.opcode == "ASL" {
+            value << count
+        } else {
+            value >> count
+        };
+        let carry = match count {
+            0 => false,
+            _ if instruction.opcode == "ASL" => shifted >> bits & 1 != 0,
+            _ => value >> (count - 1) & 1 != 0,
+        };
+        let result = shifted as u32 & size.mask();
+        self.set_logic_flags(result, size);
+        self.flags.v = i128::from(size.sign_extend(result) as i32) != shifted;
+        self.flags.c = carry;
+        self.set(dst, size, result)
+    }
+
+    fn logic_op(
+        &mut self,
+        instruction: &Instruction,
+        size: Size,
+    ) -> Result<(), AssemblyInterpreterError> {
+        let src = self.read(&self.operand(instruction, 0)?, size)?;
+        let dst = self.location(&self.operand(instruction, 1)?, size)?;
+        let value = self.get(dst, size)?;
+        let result = match &instruction.opcode[..] {
+            "AND" => value & src,
+            "OR" => value | src,
+            _ => value ^ src,
+        };
+        self.set_logic_flags(result, size);
+        self.set(dst, size, result)
+    }
+
+    /// 16 x 16 bit multiply with a 32 bit product, signed for MULS and
+    /// unsigned for MULU. The 68000 has no 32 bit multiply.
+    fn multiply_op(
+        &mut self,
+        instruction: &Instruction,
+        size: Size,
+    ) -> Result<(), AssemblyInterpreterError> {
+        if size != Size::Word {
+            return Err(Syntax(format!(
+                "{} on a 68000 is word sized",
+                instruction.opcode
+            )));
+        }
+        let src = self.read(&self.operand(instruction, 0)?, size)?;
+        let dst = self.location(&self.operand(instruction, 1)?, Size::Long)?;
+        let value = self.get(dst, size)?;
+        let product = if instruction.opcode == "MULU" {
+            src.wrapping_mul(value)
+        } else {
+            (size.sign_extend(src) as i32).wrapping_mul(size.sign_extend(value) as i32) as u32
+        };
+        self.set_logic_flags(product, Size::Long);
+        self.set(dst, Size::Long, product)
+    }
+
+    /// Signed 32 / 16 bit divide leaving the remainder in the upper word and
+    /// the quotient in the lower word. On overflow the destination is left
+    /// unchanged and V is set.
+    fn divs_op(
+        &mut self,
+        instruction: &Instruction,
+        size: Size,
+    ) -> Result<(), AssemblyInterpreterError> {
+        if size != Size::Word {
+            return Err(Syntax(String::from("DIVS on a 68000 is word sized")));
+        }
+        let src = size.sign_extend(self.read(&self.operand(instruction, 0)?, size)?) as i32;
+        let dst = self.location(&self.operand(instruction, 1)?, Size::Long)?;
+        let value = self.get(dst, Size::Long)? as i32;
+        if src == 0 {
+            return Err(DivideByZero);
+        }
+
+        let quotient = value.wrapping_div(src);
+        if quotient < i32::from(i16::MIN) || quotient > i32::from(i16::MAX) {
+            self.flags.v = true;
+            return Ok(());
         }
+        let remainder = value.wrapping_rem(src);
+        let result = ((remainder as u32 & 0xFFFF) << 16) | (quotient as u32 & 0xFFFF);
+        self.set_logic_flags(quotient as u32, Size::Word);
+        self.set(dst, Size::Long, result)
+    }
+
+    /// The low word of D0, sign extended: where the generated code leaves the
+    /// value of an expression.
+    fn d0(&self) -> i32 {
+        Size::Word.sign_extend(self.d[0]) as i32
+    }
+
+    /// The word stored at a data label.
+    fn word(&self, label: &str) -> i32 {
+        self.data(label, Size::Word)
+    }
+
+    fn byte(&self, label: &str) -> i32 {
+        self.data(label, Size::Byte)
+    }
+
+    fn long(&self, label: &str) -> i32 {
+        self.data(label, Size::Long)
+    }
+
+    fn data(&self, label: &str, size: Size) -> i32 {
+        let address = self.labels[label];
+        size.sign_extend(self.read_memory(address, size).unwrap()) as i32
     }
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::compiler::Compiler;
+    use crate::compiler::{Compiler, Options};
     use crate::reader::{Reader, ReaderArg, TestReader};
     use crate::writer::TestWriter;
-    use std::fs;
 
     #[test]
     fn given_add_expression_output_correct_answer_register_d0() {
@@ -431,9 +1047,8 @@ pub mod tests {
 
         cradle.expression();
 
-        asm_interp.eval(writer.output);
-        println!("{:?}", asm_interp);
-        assert_eq!(asm_interp.d0, Some(3));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), 3);
     }
 
     #[test]
@@ -447,9 +1062,8 @@ pub mod tests {
 
         cradle.expression();
 
-        asm_interp.eval(writer.output);
-        println!("{:?}", asm_interp);
-        assert_eq!(asm_interp.d0, Some(-1));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), -1);
     }
 
     #[test]
@@ -463,9 +1077,8 @@ pub mod tests {
 
         cradle.expression();
 
-        asm_interp.eval(writer.output);
-        println!("{:?}", asm_interp);
-        assert_eq!(asm_interp.d0, Some(4));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), 4);
     }
 
     #[test]
@@ -480,9 +1093,8 @@ pub mod tests {
 
         cradle.expression();
 
-        asm_interp.eval(writer.output);
-        println!("{:?}", asm_interp);
-        assert_eq!(asm_interp.d0, Some(3));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), 3);
     }
 
     #[test]
@@ -496,9 +1108,8 @@ pub mod tests {
 
         cradle.expression();
 
-        asm_interp.eval(writer.output);
-        println!("{:?}", asm_interp);
-        assert_eq!(asm_interp.d0, Some(9));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), 9);
     }
 
     #[test]
@@ -514,9 +1125,8 @@ pub mod tests {
 
         cradle.expression();
 
-        asm_interp.eval(writer.output);
-        println!("{:?}", asm_interp);
-        assert_eq!(asm_interp.d0, Some(14));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), 14);
     }
 
     #[test]
@@ -533,8 +1143,1292 @@ pub mod tests {
 
         cradle.expression();
 
-        asm_interp.eval(writer.output);
-        println!("{:?}", asm_interp);
-        assert_eq!(asm_interp.d0, Some(1));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), 1);
+    }
+
+    #[test]
+    fn given_multi_digit_and_radix_literals_output_correct_answer_register_d0() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("12*$10+0b1_0")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.expression();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), 194);
+    }
+
+    #[test]
+    fn given_radix_immediates_parse_same_values_as_compiler() {
+        assert_eq!(parse_number("$1F"), Some(31));
+        assert_eq!(parse_number("0b1010"), Some(10));
+        assert_eq!(parse_number("@17"), Some(15));
+        assert_eq!(parse_number("-1_000"), Some(-1000));
+    }
+
+    #[test]
+    fn given_true_relation_output_minus_one_register_d0() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader.read(ReaderArg::Raw(String::from("3 < 5"))).unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.bool_expression();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), -1);
+    }
+
+    #[test]
+    fn given_false_relation_output_zero_register_d0() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader.read(ReaderArg::Raw(String::from("5 <= 4"))).unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.bool_expression();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), 0);
+    }
+
+    #[test]
+    fn given_greater_or_equal_relation_output_minus_one_register_d0() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader.read(ReaderArg::Raw(String::from("7 >= 7"))).unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.bool_expression();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), -1);
+    }
+
+    #[test]
+    fn given_boolean_operators_output_correct_answer_register_d0() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "2 > 1 and not 3 = 4 xor 1 <> 1",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.bool_expression();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.d0(), -1);
+    }
+
+    #[test]
+    fn given_if_else_take_branch_matching_condition() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a; if 2 > 3 or false { a = 1; } else { a = 2; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_a"), 2);
+    }
+
+    #[test]
+    fn given_while_loop_run_until_condition_fails() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var i, s; i = 0; s = 0; while i < 5 { i = i + 1; s = s + i; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_s"), 15);
+    }
+
+    #[test]
+    fn given_repeat_loop_run_body_before_testing() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var i; i = 9; repeat { i = i + 2; } until i >= 7",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_i"), 11);
+    }
+
+    #[test]
+    fn given_for_loop_count_through_inclusive_bounds() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var i, s; s = 0; for i = 2 to 6 { s = s + i; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_s"), 20);
+    }
+
+    #[test]
+    fn given_for_loop_bound_at_type_maximum_stop_without_wrapping() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "byte i; word n; n = 0; for i = 120 to 127 { n = n + 1; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_n"), 8);
+        assert_eq!(asm_interp.byte("V_i"), 127);
+    }
+
+    #[test]
+    fn given_do_loop_run_body_count_times() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var s; s = 0; do 4 { s = s + 3; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_s"), 12);
+    }
+
+    #[test]
+    fn given_do_loop_with_zero_count_skip_body() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var s; s = 0; do 0 { s = s + 3; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_s"), 0);
+    }
+
+    #[test]
+    fn given_break_leave_infinite_loop() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var i; i = 0; loop { i = i + 1; if i = 5 { break; } }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_i"), 5);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_continue_skip_rest_of_for_body() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var i, s; s = 0; for i = 1 to 6 { if i = 3 { continue; } s = s + i; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_s"), 18);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_break_inside_do_loop_pop_its_counter() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var s; s = 0; do 10 { s = s + 1; if s = 3 { break; } }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_s"), 3);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_labelled_break_leave_outer_loop() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var i, n; n = 0; outer: for i = 1 to 3 { do 5 { n = n + 1; if n = 4 { break outer; } } }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_n"), 4);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_labelled_continue_resume_outer_loop() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var i, n; n = 0; outer: for i = 1 to 3 { while true { n = n + 1; continue outer; } }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_n"), 3);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_if_without_else_skip_block_when_false() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b; a = 1; if a > 1 { a = 5; } b = a;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_b"), 1);
+    }
+
+    #[test]
+    fn given_else_if_chain_take_first_matching_branch() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b; a = 7; if a < 5 { b = 1; } else if a < 10 { b = 2; } else { b = 3; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_b"), 2);
+    }
+    #[test]
+    fn given_initialised_variable_read_its_initial_value() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var x = -5, y; y = x * 2;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_y"), -10);
+    }
+    #[test]
+    fn given_variables_named_like_labels_and_registers_keep_their_own_storage() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var L00, S00, D0, SP = 7; if SP = 7 { L00 = 1; } else { L00 = 2; } D0 = SP + L00;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_L00"), 1);
+        assert_eq!(asm_interp.word("V_D0"), 8);
+    }
+    #[test]
+    fn given_shadowed_variable_inner_block_uses_its_own_storage() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a = 1, b; if a { var a = 5; b = a; a = 7; } b = b + a;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_a"), 1);
+        assert_eq!(asm_interp.word("V_b"), 6);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_reference_parameters_procedure_changes_arguments() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var x = 3, y = 9;
+                proc swap(var a, var b) { var t; t = a; a = b; b = t; }
+                proc twice(var a) { swap(a, y); swap(a, y); }
+                swap(x, y);
+                twice(x);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_x"), 9);
+        assert_eq!(asm_interp.word("V_y"), 3);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_procedures_named_like_labels_and_registers_call_the_right_code() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var n = 0;
+                proc L01() { n = n + 1; }
+                func word D0(word x) { if x > 0 { return x * 10; } return 0; }
+                L01();
+                n = n + D0(4);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_n"), 41);
+    }
+
+    #[test]
+    fn given_value_parameters_procedure_works_on_copies() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var x = 5, y;
+                proc sub(a, b, var r) { a = a - b; r = a; }
+                sub(x, 2, y);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_x"), 5);
+        assert_eq!(asm_interp.word("V_y"), 3);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_recursive_functions_compute_factorial_and_fib() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b;
+                func fact(n) { if n <= 1 { return 1; } return n * fact(n - 1); }
+                func fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }
+                a = fact(7);
+                b = fib(12);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_a"), 5040);
+        assert_eq!(asm_interp.word("V_b"), 144);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_return_inside_loops_drop_loop_stack() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var i, a, b;
+                func first(n) { for i = 1 to 10 { do 3 { if i = n { return i * 10; } } } }
+                proc note(var r) { r = 1; return; r = 2; }
+                a = first(4) + first(20);
+                note(b);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_a"), 40);
+        assert_eq!(asm_interp.word("V_b"), 1);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_recursive_function_with_locals_keep_one_copy_per_call() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var r;
+                func fib(n) {
+                    var a, b;
+                    if n < 2 { return n; }
+                    a = fib(n - 1);
+                    b = fib(n - 2);
+                    return a + b;
+                }
+                { var n = 10; r = fib(n); }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_r"), 55);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_sized_variables_compute_with_68000_widths() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "byte b = -5, c;
+                word w;
+                long l = 100000, m, q;
+                w = b * 100;
+                m = l * 3 + w;
+                q = m / 7;
+                c = byte(300);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_w"), -500);
+        assert_eq!(asm_interp.long("V_m"), 299_500);
+        assert_eq!(asm_interp.long("V_q"), 42_785);
+        assert_eq!(asm_interp.byte("V_c"), 44);
+        assert_eq!(asm_interp.byte("V_b"), -5);
+    }
+
+    #[test]
+    fn given_constants_that_fit_assigned_like_initial_values() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "byte b, c = 200, d;
+                word w, x = 40000;
+                b = 200;
+                w = 40000;
+                { byte e = 200; d = e; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.byte("V_b"), asm_interp.byte("V_c"));
+        assert_eq!(asm_interp.byte("V_d"), -56);
+        assert_eq!(asm_interp.word("V_w"), asm_interp.word("V_x"));
+        assert_eq!(asm_interp.word("V_w"), -25536);
+    }
+
+    #[test]
+    fn given_long_multiply_divide_and_mod_output_results_of_runtime_routines() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "long a = -1234567, b = 89, big = 2147483647, m, w, q, r, s, t;
+                m = a * b;
+                w = big * 3;
+                q = a / b;
+                r = a mod b;
+                s = a / -b;
+                t = 100000 mod -b;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.long("V_m"), -1234567 * 89);
+        assert_eq!(asm_interp.long("V_w"), i32::MAX.wrapping_mul(3));
+        assert_eq!(asm_interp.long("V_q"), -1234567 / 89);
+        assert_eq!(asm_interp.long("V_r"), -1234567 % 89);
+        assert_eq!(asm_interp.long("V_s"), 1234567 / 89);
+        assert_eq!(asm_interp.long("V_t"), 100000 % -89);
+    }
+
+    #[test]
+    fn given_long_divide_by_zero_trap() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "long a = 100000, b, q;
+                q = a / b;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        let result = asm_interp.eval(writer.output);
+        assert!(matches!(result, Err(DivideByZero)));
+    }
+
+    #[test]
+    fn given_sized_parameters_and_locals_pass_through_frame() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "long r;
+                byte count, k = -3;
+                func long scale(byte k, long x) { long t = x; return t * k; }
+                proc bump(var byte n) { n = byte(n + 1); }
+                r = scale(k, 70000);
+                bump(count);
+                bump(count);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.long("V_r"), -210_000);
+        assert_eq!(asm_interp.byte("V_count"), 2);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_string_literals_lay_out_bytes_and_pass_address() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "byte *a, *b;
+                byte first, ok;
+                word size;
+                proc take(var byte s) { first = s; }
+                func word length(byte *s) { word n = 0; while *(s + n) <> 0 { n = n + 1; } return n; }
+                a = \"it's, \\\"ok\\\"\\n\";
+                b = \"it's, \\\"ok\\\"\\n\";
+                take(\"Hello\");
+                size = length(\"Hello\");
+                if first = 'H' { ok = 1; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.long("V_a"), asm_interp.long("V_b"));
+        let text = (0..12)
+            .map(|i| {
+                asm_interp
+                    .read_memory(asm_interp.long("V_a") as u32 + i, Size::Byte)
+                    .unwrap() as u8
+            })
+            .collect::<Vec<u8>>();
+        assert_eq!(text, b"it's, \"ok\"\n\0");
+        assert_eq!(asm_interp.byte("V_first"), 'H' as i32);
+        assert_eq!(asm_interp.word("V_size"), 5);
+        assert_eq!(asm_interp.byte("V_ok"), 1);
+    }
+
+    #[test]
+    fn given_arrays_sort_and_sum_elements() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word a[6];
+                long total;
+                proc sort(var word v[6]) {
+                    var i, j;
+                    for i = 0 to 4 {
+                        for j = 0 to 4 - i {
+                            if v[j] > v[j + 1] {
+                                var t = v[j];
+                                v[j] = v[j + 1];
+                                v[j + 1] = t;
+                            }
+                        }
+                    }
+                }
+                func long sum(var word v[6]) {
+                    long s[2];
+                    var i;
+                    s[1] = 0;
+                    for i = 0 to 5 { s[1] = s[1] + v[i]; }
+                    return s[1];
+                }
+                a[0] = 5; a[1] = -2; a[2] = 300; a[3] = 0; a[4] = 7; a[5] = 1;
+                sort(a);
+                total = sum(a);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        let a = asm_interp.labels["V_a"];
+        let sorted = (0..6)
+            .map(|i| {
+                Size::Word.sign_extend(asm_interp.read_memory(a + 2 * i, Size::Word).unwrap())
+                    as i32
+            })
+            .collect::<Vec<i32>>();
+        assert_eq!(sorted, vec![-2, 0, 1, 5, 7, 300]);
+        assert_eq!(asm_interp.long("V_total"), 311);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_bounds_check_out_of_range_index_trap_with_chk() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a[4], i = 4; a[i] = 1;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+        cradle.set_options(Options {
+            bounds_check: true,
+            ..Options::default()
+        });
+
+        cradle.program();
+
+        let result = asm_interp.eval(writer.output);
+        assert!(matches!(result, Err(ChkException(4))));
+    }
+
+    #[test]
+    fn given_records_in_arrays_reach_fields_by_offset() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "type Vec { word x, y, z; }
+                type Body { byte tag; Vec pos; long mass; }
+                Body bodies[3];
+                long total;
+                word last, third;
+                Body *q;
+                proc shift(var Vec v, d) { v.x = v.x + d; v.z = v.z - d; }
+                func long weigh(var Body b) { return b.mass * b.pos.x; }
+                var i;
+                for i = 0 to 2 {
+                    bodies[i].tag = byte(i);
+                    bodies[i].pos.x = i + 1;
+                    bodies[i].pos.z = 10 * i;
+                    bodies[i].mass = 1000;
+                }
+                shift(bodies[2].pos, 5);
+                {
+                    Body local;
+                    local.pos.x = 3;
+                    local.mass = 7;
+                    total = weigh(local);
+                }
+                for i = 0 to 2 { total = total + weigh(bodies[i]); }
+                last = bodies[2].pos.z;
+                q = &bodies[0];
+                q = q + 2;
+                third = q^.pos.x;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.long("V_total"), 21 + 1000 * (1 + 2 + 8));
+        assert_eq!(asm_interp.word("V_last"), 15);
+        assert_eq!(asm_interp.word("V_third"), 8);
+        let body = asm_interp.labels["V_bodies"] + 2 * 12;
+        assert_eq!(asm_interp.read_memory(body, Size::Byte).unwrap(), 2);
+        assert_eq!(asm_interp.read_memory(body + 8, Size::Long).unwrap(), 1000);
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_pointers_walk_linked_list_and_scale_arithmetic() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "type Node { long value; Node *next; }
+                Node pool[4];
+                Node *head;
+                long sum, third;
+                word a = 3, b = 4, count;
+                long table[5];
+                long *p;
+                proc swap(word *x, word *y) { var t = *x; *x = *y; *y = t; }
+                func long total(Node *n) {
+                    long s = 0;
+                    while n <> nil { s = s + n^.value; n = n^.next; }
+                    return s;
+                }
+                var i;
+                head = nil;
+                for i = 0 to 3 {
+                    pool[i].value = long(i) * 100;
+                    pool[i].next = head;
+                    head = &pool[i];
+                }
+                sum = total(head);
+                swap(&a, &b);
+                p = &table[0];
+                for i = 0 to 4 { *(p + i) = i * i; }
+                p = p + 3;
+                third = *(p - 1);
+                {
+                    Node *n = head;
+                    while n { count = count + 1; n = n^.next; }
+                }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.long("V_sum"), 600);
+        assert_eq!(asm_interp.word("V_a"), 4);
+        assert_eq!(asm_interp.word("V_b"), 3);
+        assert_eq!(asm_interp.long("V_third"), 4);
+        assert_eq!(asm_interp.word("V_count"), 4);
+        assert_eq!(
+            asm_interp.long("V_head") as u32,
+            asm_interp.labels["V_pool"] + 3 * 8
+        );
+        assert_eq!(asm_interp.a[7], MEMORY_SIZE);
+    }
+
+    #[test]
+    fn given_string_assigned_to_byte_pointer_walk_its_characters() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "byte *s; byte last; word n = 0;
+                s = \"hi!\";
+                while *s <> 0 { last = *s; n = n + 1; s = s + 1; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_n"), 3);
+        assert_eq!(asm_interp.byte("V_last"), '!' as i32);
+        let end = asm_interp.long("V_s") as u32;
+        assert_eq!(asm_interp.read_memory(end, Size::Byte).unwrap(), 0);
+    }
+
+    #[test]
+    fn given_char_literals_fold_and_widen_like_numbers() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word g = '\\xFF', n;
+                byte c;
+                { word w = '\\xFF'; n = w; }
+                c = 'A' + 1;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        assert!(writer.output.contains("MOVE.B #66,D0"));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_g"), 255);
+        assert_eq!(asm_interp.word("V_n"), asm_interp.word("V_g"));
+        assert_eq!(asm_interp.byte("V_c"), 'B' as i32);
+    }
+
+    #[test]
+    fn given_constants_bound_arrays_and_loops() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "const SIZE = 5, LAST = SIZE, STEP = -3;
+                word squares[SIZE];
+                word total, down = STEP;
+                var i;
+                for i = 1 to LAST { squares[i - 1] = i * i; }
+                for i = 0 to SIZE - 1 { total = total + squares[i]; }
+                {
+                    const SIZE = 2;
+                    down = down * SIZE;
+                }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+        cradle.set_options(Options {
+            bounds_check: true,
+            ..Options::default()
+        });
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_total"), 55);
+        assert_eq!(asm_interp.word("V_down"), -6);
+        assert!(!asm_interp.labels.contains_key("SIZE"));
+    }
+
+    #[test]
+    fn given_case_statements_run_compare_chains_and_jump_tables() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "enum Color { Red, Green = 5, Blue }
+                word sparse, dense, missed;
+                var i;
+                for i = -2 to 25 {
+                    case i {
+                        Red: { sparse = sparse + 1; }
+                        Green, 10..20: { sparse = sparse + 100; }
+                        Blue: { sparse = sparse + 10000; }
+                        else { missed = missed + 1; }
+                    }
+                    case i {
+                        1: { dense = dense + 1; }
+                        2, 4: { dense = dense + 10; }
+                        5..6: { dense = dense + 100; }
+                        7: { continue; }
+                    }
+                }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        assert!(writer.output.contains("JMP (A0)"));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_sparse"), 10000 + 12 * 100 + 1);
+        assert_eq!(asm_interp.word("V_dense"), 1 + 20 + 200);
+        assert_eq!(asm_interp.word("V_missed"), 28 - 14);
+    }
+
+    #[test]
+    fn given_unary_operators_anywhere_in_expressions() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word a, b, c, d;
+                a = 2 * -3;
+                b = -(a + 1) * +2;
+                c = ~b - -a;
+                d = 5 - not 0 * - -4;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_a"), -6);
+        assert_eq!(asm_interp.word("V_b"), 10);
+        assert_eq!(asm_interp.word("V_c"), -17);
+        assert_eq!(asm_interp.word("V_d"), 9);
+    }
+
+    #[test]
+    fn given_binary_operators_chain_by_precedence() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word product, mixed, bits, chained, negmod, shifted, xored;
+                long big, bigmod;
+                word *p;
+                product = 2 * 3 * 4;
+                mixed = 100 - 20 - 3 + 17 mod 5 * 2;
+                bits = 1 << 4 | 12 & 10 ^ 3;
+                chained = 1 < 2 = 3 > 4;
+                negmod = -17 mod 5;
+                shifted = -64 >> 3;
+                big = 100000;
+                bigmod = big mod 7 + (big << 2) / 4 - big;
+                p = &mixed;
+                xored = p^ ^ 255;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_product"), 24);
+        assert_eq!(asm_interp.word("V_mixed"), 81);
+        assert_eq!(asm_interp.word("V_bits"), 16 | (12 & 10 ^ 3));
+        assert_eq!(asm_interp.word("V_chained"), 0);
+        assert_eq!(asm_interp.word("V_negmod"), -2);
+        assert_eq!(asm_interp.word("V_shifted"), -8);
+        assert_eq!(asm_interp.data("V_bigmod", Size::Long), 100000 % 7);
+        assert_eq!(asm_interp.word("V_xored"), 81 ^ 255);
+    }
+
+    #[test]
+    fn given_and_or_skip_operands_once_the_result_is_known() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word calls, taken, stored, mixed;
+                func touch(v) { calls = calls + 1; return v; }
+                if false and touch(1) { taken = taken + 1; }
+                if true or touch(1) { taken = taken + 10; }
+                if touch(1) and touch(0) or touch(-1) { taken = taken + 100; }
+                if not (touch(0) or touch(0)) and not touch(0) { taken = taken + 1000; }
+                stored = 5 > 3 and 7;
+                mixed = 0 and touch(1) xor 1 or touch(0);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_taken"), 1110);
+        assert_eq!(asm_interp.word("V_calls"), 3 + 3);
+        assert_eq!(asm_interp.word("V_stored"), -1);
+        assert_eq!(asm_interp.word("V_mixed"), -1);
+    }
+
+    #[test]
+    fn given_comparisons_branch_as_their_stored_values_test() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word a = -3, b = 2, c = 2, taken, stored;
+                long l = 100000;
+                if a < b { taken = taken | 1; }
+                if b <= c { taken = taken | 2; }
+                if a > b { taken = taken | 4; }
+                if b >= a { taken = taken | 8; }
+                if b = c { taken = taken | 16; }
+                if a <> a { taken = taken | 32; }
+                if not (l > b) { taken = taken | 64; }
+                stored = (a < b) & 1 | (b <= c) & 2 | (a > b) & 4 | (b >= a) & 8
+                    | (b = c) & 16 | (a <> a) & 32 | (not (l > b)) & 64;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        assert!(!writer.output.contains("TST"));
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_taken"), 1 | 2 | 8 | 16);
+        assert_eq!(asm_interp.word("V_stored"), asm_interp.word("V_taken"));
+    }
+
+    #[test]
+    fn given_not_stored_gives_the_truth_value_an_if_tests() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word a = 5, z, b, c, d, e, taken;
+                b = not a;
+                c = not z;
+                d = not 5;
+                e = not not a;
+                if not a { taken = 1; } else { taken = 2; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_b"), 0);
+        assert_eq!(asm_interp.word("V_c"), -1);
+        assert_eq!(asm_interp.word("V_d"), 0);
+        assert_eq!(asm_interp.word("V_e"), -1);
+        assert_eq!(asm_interp.word("V_taken"), 2);
+    }
+
+    #[test]
+    fn given_constant_expressions_fold_to_the_values_code_computes() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "const N = 10;
+                word folded, computed, n = N, taken;
+                long big;
+                folded = (N + 2) * 3 - -17 mod 5 + (N > 3) + ~N;
+                computed = (n + 2) * 3 - -17 mod 5 + (n > 3) + ~n;
+                big = (long(N) << 12) * 40;
+                if 0 and n { taken = 1; } else { taken = 2; }
+                while N and taken < 5 { taken = taken + 1; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_folded"), asm_interp.word("V_computed"));
+        assert_eq!(asm_interp.word("V_folded"), 36 + 2 - 1 - 11);
+        assert_eq!(asm_interp.data("V_big", Size::Long), 10 * 4096 * 40);
+        assert_eq!(asm_interp.word("V_taken"), 5);
+    }
+
+    #[test]
+    fn given_constants_beyond_signed_range_fold_as_registers_hold_them() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "long all = $FFFFFFFF, top = $80000000, two = 2, zero;
+                long folded, computed, shifted, shifted2;
+                word less, less2, order, order2;
+                folded = $FFFFFFFF / 2 + $FFFFFFFF mod 7;
+                computed = all / two + all mod 7;
+                shifted = $80000000 >> 4;
+                shifted2 = top >> 4;
+                less = $FFFFFFFF < 0;
+                less2 = all < zero;
+                order = $80000000 < $7FFFFFFF;
+                order2 = top < top - 1;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.long("V_folded"), asm_interp.long("V_computed"));
+        assert_eq!(asm_interp.long("V_folded"), -1);
+        assert_eq!(asm_interp.long("V_shifted"), asm_interp.long("V_shifted2"));
+        assert_eq!(asm_interp.word("V_less"), asm_interp.word("V_less2"));
+        assert_eq!(asm_interp.word("V_less"), -1);
+        assert_eq!(asm_interp.word("V_order"), asm_interp.word("V_order2"));
+        assert_eq!(asm_interp.word("V_order"), -1);
+    }
+
+    #[test]
+    fn given_conditional_expressions_yield_the_chosen_value() {
+        let mut asm_interp = AssemblyInterpreter::new();
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word a = 7, b = -3, larger, sign, calls, picked;
+                long wide;
+                word *p;
+                func touch(v) { calls = calls + 1; return v; }
+                larger = 10 + (if a > b then a else b) * 2;
+                sign = b < 0 ? -1 : b = 0 ? 0 : 1;
+                wide = a > 5 ? 100000 : a;
+                picked = (a = 7 ? touch(1) : touch(2)) + (if false then touch(4) else 8);
+                p = a > 0 ? &a : nil;
+                if p <> nil ? p^ = 7 : false { picked = picked + 100; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.init();
+
+        cradle.program();
+
+        asm_interp.eval(writer.output).unwrap();
+        assert_eq!(asm_interp.word("V_larger"), 24);
+        assert_eq!(asm_interp.word("V_sign"), -1);
+        assert_eq!(asm_interp.data("V_wide", Size::Long), 100000);
+        assert_eq!(asm_interp.word("V_calls"), 1);
+        assert_eq!(asm_interp.word("V_picked"), 109);
     }
 }
@@ -1,455 +1,3735 @@
-use crate::reader::{Reader, TestReader};
-use crate::writer::{TestWriter, Writer};
+use crate::reader::Reader;
+use crate::scanner::{Keyword, Operator, Punct, Scanner, Span, Token};
+use crate::symbol_table::{
+    Location, Param, Passing, Record, Symbol, SymbolKind, SymbolTable, Type,
+};
+use crate::writer::Writer;
 use std::io;
 
+/// Largest literal that fits the 32-bit operand of a `.L` instruction.
+const MAX_LITERAL: i64 = 0xFFFF_FFFF;
+
+/// The binary operators, from the loosest binding to the tightest.
+const PRECEDENCE: &[&[Operator]] = &[
+    &[
+        Operator::Equals,
+        Operator::NotEquals,
+        Operator::Less,
+        Operator::LessEquals,
+        Operator::Greater,
+        Operator::GreaterEquals,
+    ],
+    &[Operator::Bar],
+    &[Operator::Caret],
+    &[Operator::Ampersand],
+    &[Operator::ShiftLeft, Operator::ShiftRight],
+    &[Operator::Plus, Operator::Minus],
+    &[Operator::Star, Operator::Slash, Operator::Mod],
+];
+
+/// Fewest case labels worth a jump table, and the most entries one may have.
+const JUMP_TABLE_MIN: i64 = 4;
+const JUMP_TABLE_MAX: i64 = 256;
+
+/// Runtime routines for the long arithmetic the 68000 has no instruction
+/// for, emitted after the program when it calls them. Both take their
+/// operands in D7 and D0. RT_MULS leaves the low 32 bits of the product in
+/// D0; RT_DIVS leaves the quotient in D7 and the remainder in D1, and traps
+/// on a zero divisor as DIVS does.
+const RUNTIME: &[(&str, &[&str])] = &[
+    (
+        "RT_MULS",
+        &[
+            "RT_MULS:",
+            "MOVE.L D1,-(SP)",
+            "MOVE.L D2,-(SP)",
+            "MOVE.L D0,D1",
+            "SWAP D1",
+            "MULU D7,D1",
+            "MOVE.L D7,D2",
+            "SWAP D2",
+            "MULU D0,D2",
+            "ADD.W D2,D1",
+            "SWAP D1",
+            "CLR.W D1",
+            "MULU D7,D0",
+            "ADD.L D1,D0",
+            "MOVE.L (SP)+,D2",
+            "MOVE.L (SP)+,D1",
+            "RTS",
+        ],
+    ),
+    (
+        "RT_DIVS",
+        &[
+            "RT_DIVS:",
+            "TST.L D0",
+            "BNE RT_DIVS1",
+            "DIVS D0,D7",
+            "RT_DIVS1:",
+            "MOVE.L D2,-(SP)",
+            "MOVE.L D3,-(SP)",
+            "MOVE.L D4,-(SP)",
+            "MOVE.L D0,D2",
+            "MOVE.L D7,D3",
+            "EOR.L D2,D3",
+            "MOVE.L D7,D4",
+            "TST.L D7",
+            "BPL RT_DIVS2",
+            "NEG.L D7",
+            "RT_DIVS2:",
+            "TST.L D2",
+            "BPL RT_DIVS3",
+            "NEG.L D2",
+            "RT_DIVS3:",
+            "CLR.L D1",
+            "MOVE.W #31,D0",
+            "RT_DIVS4:",
+            "ASL.L #1,D1",
+            "TST.L D7",
+            "BPL RT_DIVS5",
+            "ADDQ.L #1,D1",
+            "RT_DIVS5:",
+            "ASL.L #1,D7",
+            "CMP.L D2,D1",
+            "BCS RT_DIVS6",
+            "SUB.L D2,D1",
+            "ADDQ.L #1,D7",
+            "RT_DIVS6:",
+            "DBRA D0,RT_DIVS4",
+            "TST.L D3",
+            "BPL RT_DIVS7",
+            "NEG.L D7",
+            "RT_DIVS7:",
+            "TST.L D4",
+            "BPL RT_DIVS8",
+            "NEG.L D1",
+            "RT_DIVS8:",
+            "MOVE.L (SP)+,D4",
+            "MOVE.L (SP)+,D3",
+            "MOVE.L (SP)+,D2",
+            "RTS",
+        ],
+    ),
+];
+
+/// Settings that change what the compiler emits.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    /// Copy source comments into the output as `*` comment lines.
+    pub comments: bool,
+    /// Check array indexes with CHK, which traps when one is out of range.
+    pub bounds_check: bool,
+}
+
+/// The labels of an enclosing loop, passed down through `block` and
+/// `statement` so `break` and `continue` know where to branch.
+#[derive(Debug, Clone)]
+pub struct LoopLabels {
+    name: Option<String>,
+    exit: String,
+    next: String,
+    /// Bytes the loop keeps on the stack while its body runs, which must be
+    /// popped when jumping out of it.
+    stack: usize,
+}
+
+impl LoopLabels {
+    fn new(name: Option<String>, exit: &str, next: &str, stack: usize) -> Self {
+        Self {
+            name,
+            exit: exit.to_string(),
+            next: next.to_string(),
+            stack,
+        }
+    }
+}
+
+/// A condition read ahead of its code, so that `and` and `or` can branch
+/// straight to where their result is known.
+#[derive(Debug)]
+enum Condition {
+    /// Code leaving a value in D0 (or A0 for a pointer) that is true when
+    /// it is not zero.
+    Test(Vec<String>, Type),
+    /// Code ending in a compare, true when its flags meet the condition
+    /// code.
+    Compare(Vec<String>, &'static str),
+    Not(Box<Condition>),
+    /// A value known while compiling, which needs no test.
+    Constant(i64, Type),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+}
+
 #[derive(Debug)]
 pub struct Compiler<'a, R: Reader, W: Writer> {
-    lookahead: Option<char>,
-    source: Vec<char>,
-    counter: usize,
+    options: Options,
+    lookahead: Token,
+    span: Span,
+    scanner: Scanner,
     reader: R,
-    writer: Box<&'a mut W>,
+    writer: &'a mut W,
     l_count: usize,
+    symbols: SymbolTable,
+    /// The epilogue label and result type of the procedure being compiled.
+    routine: Option<(String, Type)>,
+    /// Labels of the variables in the data section in declaration order,
+    /// with their types and initial values.
+    globals: Vec<(String, Type, Option<i64>)>,
+    /// String literals in the order first seen; each is stored once.
+    strings: Vec<Vec<u8>>,
+    /// Bytes of the current stack frame used by the locals in scope, and
+    /// the most the frame has needed so far.
+    frame: usize,
+    frame_size: usize,
+    /// Code held back until the size of its frame is known, innermost last.
+    buffers: Vec<Vec<String>>,
+    /// A constant that is the value of the expression just compiled. Its
+    /// load is held back until another instruction is emitted, so that an
+    /// operator applied to it can be worked out while compiling instead.
+    pending: Option<(i64, Type)>,
+    /// The condition code of a comparison just compiled. Like a pending
+    /// constant, its value is only set in D0 when another instruction is
+    /// emitted, so that a test of it can branch on the flags instead.
+    compare: Option<&'static str>,
+    /// The runtime routines the program calls, in the order first called.
+    runtime: Vec<&'static str>,
 }
 
 impl<'a, R: Reader, W: Writer> Compiler<'a, R, W> {
     pub fn new(reader: R, writer: &'a mut W) -> Self {
         Compiler {
-            lookahead: None,
-            source: Vec::new(),
-            counter: 0,
-            reader: reader,
-            writer: Box::new(writer),
+            options: Options::default(),
+            lookahead: Token::Eof,
+            span: Span {
+                start: 0,
+                end: 0,
+                line: 1,
+                column: 1,
+            },
+            scanner: Scanner::new(Vec::new()),
+            reader,
+            writer,
             l_count: 0,
+            symbols: SymbolTable::new(),
+            routine: None,
+            globals: Vec::new(),
+            strings: Vec::new(),
+            frame: 0,
+            frame_size: 0,
+            buffers: Vec::new(),
+            pending: None,
+            compare: None,
+            runtime: Vec::new(),
         }
     }
 
+    pub fn set_options(&mut self, options: Options) {
+        self.options = options;
+    }
+
     pub fn init(&mut self) {
         self.read().unwrap();
-        self.lookahead = Some(self.source[self.counter]);
+        self.next();
     }
 
     fn read(&mut self) -> io::Result<()> {
-        self.source = self.reader.get_buffer();
+        self.scanner = Scanner::new(self.reader.get_buffer());
         Ok(())
     }
 
-    fn get_char(&mut self) -> io::Result<()> {
-        if self.source.len() < 1 {
-            self.read().unwrap();
+    fn next(&mut self) {
+        match self.scanner.next_token() {
+            Ok((token, span)) => {
+                self.lookahead = token;
+                self.span = span;
+                self.pass_comments();
+            }
+            Err(error) => {
+                self.span = error.span;
+                self.abort(error.message);
+            }
+        }
+    }
+
+    /// Copies the comments that preceded the lookahead into the output, so
+    /// they land just before the code generated for it.
+    fn pass_comments(&mut self) {
+        let comments = self.scanner.take_comments();
+        if !self.options.comments {
+            return;
         }
-        if self.counter >= self.source.len() - 1 {
-            self.lookahead = None;
-            return Ok(());
+        for comment in comments {
+            if comment.is_empty() {
+                self.emit_line(String::from("*"));
+            } else {
+                self.emit_line(format!("* {}", comment));
+            }
         }
-        self.counter += 1;
-        self.lookahead = Some(self.source[self.counter]);
-        Ok(())
     }
 
     fn error(&mut self, message: String) {
-        self.writer
-            .writeln(format!("\n\x1b[0;31mError: {}\x1b[0m", message));
+        self.writer.writeln(format!(
+            "\n\x1b[0;31mError: {} at line {}, column {}\x1b[0m",
+            message, self.span.line, self.span.column
+        ));
+    }
+
+    fn abort(&mut self, message: String) -> ! {
+        self.error(message.clone());
+        panic!("{}", message);
+    }
+
+    fn expected(&mut self, expected: String) -> ! {
+        self.abort(format!("{} expected", expected));
+    }
+
+    fn emit_line(&mut self, s: String) {
+        self.flush();
+        self.write_line(s);
+    }
+
+    fn write_line(&mut self, s: String) {
+        match self.buffers.last_mut() {
+            Some(buffer) => buffer.push(s),
+            None => self.writer.writeln(s),
+        }
+    }
+
+    /// Emits the load of a pending constant, or sets D0 from a pending
+    /// comparison.
+    fn flush(&mut self) {
+        if let Some((value, ty)) = self.pending.take() {
+            self.write_line(format!("MOVE{} #{},D0", ty.suffix(), value));
+        }
+        if let Some(code) = self.compare.take() {
+            self.write_line(format!("S{} D0", code));
+            self.write_line(String::from("EXT.W D0"));
+        }
+    }
+
+    /// Starts holding back code, once any pending value is loaded.
+    fn begin_buffer(&mut self) {
+        self.flush();
+        self.buffers.push(Vec::new());
+    }
+
+    fn end_buffer(&mut self) -> Vec<String> {
+        self.flush();
+        self.buffers.pop().unwrap()
+    }
+
+    fn match_token(&mut self, x: Token) {
+        if self.lookahead == x {
+            self.next();
+        } else {
+            self.expected(format!("\"{}\"", x));
+        }
+    }
+
+    fn match_punct(&mut self, x: Punct) {
+        self.match_token(Token::Punct(x));
+    }
+
+    fn match_operator(&mut self, x: Operator) {
+        self.match_token(Token::Operator(x));
+    }
+
+    fn match_keyword(&mut self, x: Keyword) {
+        self.match_token(Token::Keyword(x));
+    }
+
+    fn is_punct(&self, x: Punct) -> bool {
+        self.lookahead == Token::Punct(x)
+    }
+
+    fn get_name(&mut self) -> String {
+        match self.lookahead.clone() {
+            Token::Ident(name) => {
+                self.next();
+                name
+            }
+            _ => self.expected(String::from("Name")),
+        }
+    }
+
+    /// Reads a string literal and returns the label of its copy in the
+    /// string pool.
+    fn get_string(&mut self) -> String {
+        let bytes = match self.lookahead.clone() {
+            Token::Str(bytes) => bytes,
+            _ => self.expected(String::from("String")),
+        };
+        self.next();
+
+        let index = match self.strings.iter().position(|s| *s == bytes) {
+            Some(index) => index,
+            None => {
+                self.strings.push(bytes);
+                self.strings.len() - 1
+            }
+        };
+        format!("S{:0>2}", index)
+    }
+
+    fn get_num(&mut self) -> i64 {
+        let num = match self.lookahead {
+            Token::Number(num) => num,
+            _ => self.expected(String::from("Integer")),
+        };
+
+        if num > MAX_LITERAL {
+            self.abort(format!(
+                "Integer literal {} does not fit in a 32-bit operand",
+                num
+            ));
+        }
+
+        self.next();
+        num
+    }
+
+    /// The smallest size that holds a literal.
+    fn literal_type(num: i64) -> Type {
+        if (-128..=127).contains(&num) {
+            Type::Byte
+        } else if (-32768..=32767).contains(&num) {
+            Type::Word
+        } else {
+            Type::Long
+        }
+    }
+
+    /// Loads a literal at the smallest size that holds it.
+    fn load_num(&mut self, num: i64) -> Type {
+        let ty = Self::literal_type(num);
+        self.load_constant(num, ty.clone());
+        ty
+    }
+
+    /// Loads a value known while compiling. The load is left pending so
+    /// the operator that follows can fold it.
+    fn load_constant(&mut self, value: i64, ty: Type) {
+        self.flush();
+        self.pending = Some((value, ty));
+    }
+
+    /// Pushes D0, or A0 for a pointer. The stack pointer stays even, so a
+    /// byte takes a word.
+    fn push(&mut self, ty: &Type) {
+        let register = Self::register(ty);
+        self.emit_line(format!("MOVE{} {},-(SP)", ty.suffix(), register));
+    }
+
+    /// The register an expression of type `ty` leaves its value in.
+    /// Pointers are kept in A0, ready to be dereferenced.
+    fn register(ty: &Type) -> &'static str {
+        match ty {
+            Type::Pointer(_) => "A0",
+            _ => "D0",
+        }
+    }
+
+    /// Sign extends a register from one integer type to a wider one. A
+    /// constant still pending for D0 is retyped, so it is loaded at the
+    /// wider size instead.
+    fn convert(&mut self, from: &Type, to: &Type, register: &str) {
+        if register == "D0" && from < to {
+            if let Some((value, _)) = self.pending {
+                self.pending = Some((value, to.clone()));
+                return;
+            }
+        }
+        if *from == Type::Byte && *to > Type::Byte {
+            self.emit_line(format!("EXT.W {}", register));
+        }
+        if *from < Type::Long && *to == Type::Long {
+            self.emit_line(format!("EXT.L {}", register));
+        }
+    }
+
+    /// Widens D0 to the type it is stored as. Narrowing needs a cast, unless
+    /// D0 is a constant whose value fits, as with an initial value. A
+    /// pointer is moved to D0 so it is stored like any other value; `nil`
+    /// can be stored in any pointer.
+    fn coerce(&mut self, from: &Type, to: &Type, target: &str) {
+        if let Some((value, _)) = self.pending {
+            if from.is_scalar() && to.fits(value) {
+                self.pending = Some((value, to.clone()));
+                return;
+            }
+        }
+        let fits = match (from, to) {
+            (Type::Pointer(pointee), Type::Pointer(_)) => from == to || **pointee == Type::Void,
+            _ => from.is_scalar() && to.is_scalar() && from <= to,
+        };
+        if !fits {
+            self.abort(format!(
+                "Type mismatch: cannot assign {} to {} {}",
+                from, to, target
+            ));
+        }
+        if to.is_pointer() {
+            self.emit_line(String::from("MOVE.L A0,D0"));
+        } else {
+            self.convert(from, to, "D0");
+        }
+    }
+
+    /// Rejects anything but a number as the operand of an arithmetic or
+    /// logical operator.
+    fn check_number(&mut self, ty: &Type) {
+        if !ty.is_scalar() {
+            self.abort(format!("Type mismatch: {} is not a number", ty));
+        }
+    }
+
+    /// Pops the left operand of a binary operator into D7 and widens both
+    /// operands to the wider of their types. Arithmetic is done on at least
+    /// words, so the result type is never a byte.
+    fn pop_operands(&mut self, left: &Type, right: &Type) -> Type {
+        self.check_number(left);
+        self.check_number(right);
+        let ty = left.max(right).clone().max(Type::Word);
+        self.convert(right, &ty, "D0");
+        self.emit_line(format!("MOVE{} (SP)+,D7", left.suffix()));
+        self.convert(left, &ty, "D7");
+        ty
+    }
+
+    /// Divides D7 by D0, leaving the quotient in the lower word of D7 or, for
+    /// a long, all of it. A word divide takes a long dividend.
+    fn divide(&mut self, ty: &Type) {
+        match ty {
+            Type::Long => self.call_runtime("RT_DIVS"),
+            _ => {
+                self.emit_line(String::from("EXT.L D7"));
+                self.emit_line(format!("DIVS{} D0,D7", ty.suffix()));
+            }
+        }
+    }
+
+    /// Leaves the remainder of D7 divided by D0 in D0. A word divide leaves
+    /// it in the upper word of D7, the long divide routine in D1.
+    fn remainder(&mut self, ty: &Type) {
+        self.divide(ty);
+        if *ty == Type::Long {
+            self.emit_line(String::from("MOVE.L D1,D0"));
+            return;
+        }
+        self.emit_line(String::from("SWAP D7"));
+        self.emit_line(String::from("MOVE.W D7,D0"));
+    }
+
+    /// Calls one of the runtime routines, which is emitted after the program.
+    fn call_runtime(&mut self, name: &'static str) {
+        if !self.runtime.contains(&name) {
+            self.runtime.push(name);
+        }
+        self.emit_line(format!("BSR {}", name));
+    }
+
+    fn ident(&mut self) -> Type {
+        let name = self.get_name();
+        if self.is_punct(Punct::LParen) {
+            let symbol = self.lookup_procedure(&name);
+            if symbol.ty == Type::Void {
+                self.abort(format!("{} is a procedure and has no value", name));
+            }
+            self.call(&symbol);
+            symbol.ty
+        } else {
+            let symbol = self.lookup_value(&name);
+            let (location, ty) = self.designator(&symbol);
+            self.check_scalar(&name, &ty);
+            self.load(&location, &ty);
+            ty
+        }
+    }
+
+    /// Follows the indexes and field selections after a variable's name to
+    /// the location they pick out, and returns it with its type. Fields of a
+    /// variable in the frame are reached by displacement from A6; anything
+    /// else has its address put in A0 first.
+    fn designator(&mut self, symbol: &Symbol) -> (Location, Type) {
+        let mut location = symbol.location.clone();
+        let mut ty = symbol.ty.clone();
+        loop {
+            (location, ty) = match self.lookahead {
+                Token::Punct(Punct::LBracket) => self.element(&symbol.name, location, ty),
+                Token::Punct(Punct::Dot) => self.field(&symbol.name, location, ty),
+                Token::Operator(Operator::Caret) if ty.is_pointer() => self.follow(location, ty),
+                _ => return (location, ty),
+            };
+        }
+    }
+
+    /// Leaves the address of the array element `[index]` in A0. The index
+    /// is computed first, as it may use A0 itself, and is checked with CHK
+    /// when bounds checking is on.
+    fn element(&mut self, name: &str, location: Location, ty: Type) -> (Location, Type) {
+        let (ty, length) = match ty {
+            Type::Array(ty, length) => (*ty, length),
+            _ => self.abort(format!("{} is not an array", name)),
+        };
+        if let Location::Indirect(_) = location {
+            self.emit_line(String::from("MOVE.L A0,-(SP)"));
+        }
+        self.match_punct(Punct::LBracket);
+        let index = self.boolean();
+        self.coerce(&index, &Type::Word, "array index");
+        self.match_punct(Punct::RBracket);
+        if self.options.bounds_check {
+            self.emit_line(format!("CHK.W #{},D0", length - 1));
+        }
+        let displacement = match location {
+            Location::Indirect(displacement) => {
+                self.emit_line(String::from("MOVE.L (SP)+,A0"));
+                displacement
+            }
+            _ => {
+                self.address(&location);
+                0
+            }
+        };
+        self.index_address(displacement, ty.bytes(), &Type::Word);
+        (Location::Indirect(0), ty)
+    }
+
+    /// Adds D0 times `size` to A0. `index` is the type D0 holds. Sizes that
+    /// are powers of two are shifted into place; others are multiplied out.
+    fn index_address(&mut self, displacement: i32, size: usize, index: &Type) {
+        if size == 1 {
+            let register = format!("D0{}", index.suffix());
+            self.emit_line(format!("LEA {}(A0,{}),A0", displacement, register));
+            return;
+        }
+        let shift = size.trailing_zeros();
+        if size.is_power_of_two() && shift <= 8 {
+            if *index == Type::Word {
+                self.emit_line(String::from("EXT.L D0"));
+            }
+            self.emit_line(format!("ASL.L #{},D0", shift));
+        } else if *index == Type::Word {
+            self.emit_line(format!("MULS.W #{},D0", size));
+        } else {
+            self.emit_line(format!("MOVE.L #{},D7", size));
+            self.call_runtime("RT_MULS");
+        }
+        self.emit_line(format!("LEA {}(A0,D0.L),A0", displacement));
+    }
+
+    /// Selects the field `.name` of a record by adding its offset to the
+    /// displacement that reaches the record.
+    fn field(&mut self, name: &str, location: Location, ty: Type) -> (Location, Type) {
+        let record = match ty {
+            Type::Record(record) => record,
+            _ => self.abort(format!("{} is not a record", name)),
+        };
+        self.match_punct(Punct::Dot);
+        let field_name = self.get_name();
+        let field = match record.field(&field_name) {
+            Some(field) => field.clone(),
+            None => self.abort(format!("{} has no field {}", record.name, field_name)),
+        };
+        let offset = field.offset as i32;
+        let location = match location {
+            Location::Frame(displacement) => Location::Frame(displacement + offset),
+            Location::Indirect(displacement) => Location::Indirect(displacement + offset),
+            location => {
+                self.address(&location);
+                Location::Indirect(offset)
+            }
+        };
+        (location, field.ty)
+    }
+
+    /// Follows the pointer at `location` with `^`.
+    fn follow(&mut self, location: Location, ty: Type) -> (Location, Type) {
+        self.match_operator(Operator::Caret);
+        let pointee = self.pointee(&ty);
+        self.load(&location, &ty);
+        (Location::Indirect(0), pointee)
+    }
+
+    /// Rejects an array or record named where a number is needed.
+    fn check_scalar(&mut self, name: &str, ty: &Type) {
+        match ty {
+            Type::Array(..) => self.abort(format!("{} is an array and needs an index", name)),
+            Type::Record(_) => self.abort(format!("{} is a record and needs a field", name)),
+            _ => (),
+        }
+    }
+
+    /// Reads `byte`, `word` or `long` if it is next.
+    fn type_keyword(&mut self) -> Option<Type> {
+        let ty = match self.lookahead {
+            Token::Keyword(Keyword::Byte) => Type::Byte,
+            Token::Keyword(Keyword::Word) => Type::Word,
+            Token::Keyword(Keyword::Long) => Type::Long,
+            _ => return None,
+        };
+        self.next();
+        Some(ty)
+    }
+
+    /// Compiles a cast such as `long(x)`. Widening sign extends; narrowing
+    /// just keeps the low part of D0.
+    fn cast(&mut self) -> Type {
+        let ty = self.type_keyword().unwrap();
+        self.match_punct(Punct::LParen);
+        let from = self.boolean();
+        self.match_punct(Punct::RParen);
+        if from.is_pointer() && ty == Type::Long {
+            self.emit_line(String::from("MOVE.L A0,D0"));
+        } else if let Some((value, _)) = self.pending.take() {
+            let value = match ty {
+                Type::Byte => i64::from(value as i8),
+                Type::Word => i64::from(value as i16),
+                _ => i64::from(value as i32),
+            };
+            self.load_constant(value, ty.clone());
+        } else {
+            self.check_number(&from);
+            self.convert(&from, &ty, "D0");
+        }
+        ty
+    }
+
+    /// Compiles `&x`, leaving the address of a variable, element or field
+    /// in A0.
+    fn address_of(&mut self) -> Type {
+        self.match_operator(Operator::Ampersand);
+        let name = self.get_name();
+        let symbol = self.lookup_target(&name);
+        let (location, ty) = self.designator(&symbol);
+        self.address(&location);
+        Type::pointer_to(ty)
+    }
+
+    /// Reads `*` and the factor after it, which must be a pointer. Leaves
+    /// the address in A0 and returns the type it points to.
+    fn pointer_target(&mut self) -> Type {
+        self.match_operator(Operator::Star);
+        let ty = self.factor();
+        let target = self.pointee(&ty);
+        self.check_scalar(&format!("The target of {}", ty), &target);
+        target
+    }
+
+    /// The type a pointer points to, with a record named as the target
+    /// looked up.
+    fn pointee(&mut self, ty: &Type) -> Type {
+        let pointee = match ty {
+            Type::Pointer(pointee) => (**pointee).clone(),
+            _ => self.abort(format!("Type mismatch: {} is not a pointer", ty)),
+        };
+        match pointee {
+            Type::Named(name) => self.named_type(&name),
+            Type::Void => self.abort(String::from("nil cannot be dereferenced")),
+            pointee => pointee,
+        }
+    }
+
+    /// Compiles a factor behind any prefix `-`, `+`, `~` or `not`. `~` and
+    /// `not` both complement every bit; `not` applies to a single factor
+    /// here, while at the start of a condition it takes a whole relation.
+    fn unary(&mut self) -> Type {
+        let op = match self.lookahead {
+            Token::Operator(Operator::Minus)
+            | Token::Operator(Operator::Tilde)
+            | Token::Keyword(Keyword::Not) => Some(self.lookahead.clone()),
+            Token::Operator(Operator::Plus) => None,
+            _ => return self.factor(),
+        };
+        self.next();
+        let ty = self.unary();
+        self.check_number(&ty);
+        match op {
+            Some(op) => self.complement(&op, &ty),
+            None => ty,
+        }
+    }
+
+    /// Applies `-` (NEG) or `~`/`not` (NOT) to D0, or to the pending
+    /// constant. A folded constant takes the smallest type that holds it.
+    fn complement(&mut self, op: &Token, ty: &Type) -> Type {
+        let negate = *op == Token::Operator(Operator::Minus);
+        let Some((value, _)) = self.pending.take() else {
+            let instruction = if negate { "NEG" } else { "NOT" };
+            self.emit_line(format!("{}{} D0", instruction, ty.suffix()));
+            return ty.clone();
+        };
+        let result = if negate {
+            value
+                .checked_neg()
+                .filter(|result| Type::Long.fits(*result))
+        } else if Self::in_range(ty, value) {
+            Some(!value)
+        } else {
+            // An unsigned literal above the signed range: NOT keeps it
+            // within the same width.
+            Some(!value + (1 << (8 * ty.bytes())))
+        };
+        let Some(result) = result else {
+            self.abort(format!("{}({}) overflows a {}", op, value, Type::Long));
+        };
+        let ty = Self::literal_type(result);
+        self.load_constant(result, ty.clone());
+        ty
+    }
+
+    fn factor(&mut self) -> Type {
+        match self.lookahead {
+            Token::Punct(Punct::LParen) => {
+                self.match_punct(Punct::LParen);
+                let ty = self.boolean();
+                self.match_punct(Punct::RParen);
+                ty
+            }
+            Token::Keyword(Keyword::Byte)
+            | Token::Keyword(Keyword::Word)
+            | Token::Keyword(Keyword::Long) => self.cast(),
+            Token::Ident(_) => self.ident(),
+            Token::Operator(Operator::Ampersand) => self.address_of(),
+            Token::Operator(Operator::Star) => {
+                let ty = self.pointer_target();
+                self.load(&Location::Indirect(0), &ty);
+                ty
+            }
+            Token::Keyword(Keyword::If) => self.conditional(),
+            Token::Keyword(Keyword::Nil) => {
+                self.match_keyword(Keyword::Nil);
+                self.emit_line(String::from("SUBA.L A0,A0"));
+                Type::pointer_to(Type::Void)
+            }
+            Token::Char(c) => {
+                self.next();
+                self.load_constant(i64::from(c), Type::Byte);
+                Type::Byte
+            }
+            Token::Str(_) => {
+                let label = self.get_string();
+                self.emit_line(format!("LEA {}(PC),A0", label));
+                Type::pointer_to(Type::Byte)
+            }
+            _ => {
+                let num = self.get_num();
+                self.load_num(num)
+            }
+        }
+    }
+
+    /// Moves the pointer on the stack by the count in D0, in units of the
+    /// type it points to.
+    fn offset_pointer(&mut self, pointer: &Type, count: &Type, subtract: bool) -> Type {
+        let size = self.pointee(pointer).bytes();
+        self.check_number(count);
+        self.convert(count, &Type::Long, "D0");
+        if subtract {
+            self.emit_line(String::from("NEG.L D0"));
+        }
+        self.emit_line(String::from("MOVE.L (SP)+,A0"));
+        self.index_address(0, size, &Type::Long);
+        pointer.clone()
+    }
+
+    /// The precedence level of the binary operator in the lookahead, if it
+    /// is one.
+    fn binary_level(&self) -> Option<(usize, Operator)> {
+        match self.lookahead {
+            Token::Operator(op) => PRECEDENCE
+                .iter()
+                .position(|level| level.contains(&op))
+                .map(|level| (level, op)),
+            _ => None,
+        }
+    }
+
+    /// Compiles an expression and loads its value into D0. Statements
+    /// leave a constant result pending so it is loaded at the size it is
+    /// used at; this is the entry point for tests.
+    #[cfg(test)]
+    pub fn expression(&mut self) -> Type {
+        let ty = self.binary(0);
+        self.flush();
+        ty
+    }
+
+    /// Compiles an expression of binary operators by precedence climbing:
+    /// each operand extends to take in operators that bind more tightly
+    /// than the one before it, so every level is left associative. The left
+    /// operand waits on the stack while the right one is computed.
+    fn binary(&mut self, min: usize) -> Type {
+        let mut left = self.unary();
+        while let Some((level, op)) = self.binary_level() {
+            if level < min {
+                break;
+            }
+            let Some((value, ty)) = self.pending.take() else {
+                self.push(&left);
+                self.next();
+                let right = self.binary(level + 1);
+                left = self.operation(op, &left, &right);
+                continue;
+            };
+
+            // The left operand is a constant, so the right one's code is
+            // held back until it is known whether the two can be folded.
+            self.begin_buffer();
+            self.next();
+            let right = self.binary(level + 1);
+            let folded = self.pending.take();
+            let code = self.end_buffer();
+            let only_comments = code.iter().all(|line| line.starts_with('*'));
+            match folded {
+                Some(constant) if only_comments => {
+                    self.emit_lines(code);
+                    let (value, ty) = self.fold(op, (value, ty), constant);
+                    self.load_constant(value, ty.clone());
+                    left = ty;
+                }
+                folded => {
+                    // Loaded at the width of the operation, so that it
+                    // needs no widening once popped.
+                    let ty = if right.is_scalar() {
+                        ty.max(right.clone()).max(Type::Word)
+                    } else {
+                        ty
+                    };
+                    self.load_constant(value, ty.clone());
+                    self.push(&ty);
+                    self.emit_lines(code);
+                    self.pending = folded;
+                    left = self.operation(op, &ty, &right);
+                }
+            }
+        }
+        left
+    }
+
+    /// Works out a binary operator on two constants as the generated code
+    /// would: at the width of the operation, with each operand read as the
+    /// signed number a register of that width holds. A result the code
+    /// would get wrong is an error.
+    fn fold(&mut self, op: Operator, left: (i64, Type), right: (i64, Type)) -> (i64, Type) {
+        let ty = left.1.max(right.1).max(Type::Word);
+        let (a, b) = (Self::signed(&ty, left.0), Self::signed(&ty, right.0));
+        if Self::condition_code(op).is_some() {
+            let holds = match op {
+                Operator::Equals => a == b,
+                Operator::NotEquals => a != b,
+                Operator::Less => a < b,
+                Operator::LessEquals => a <= b,
+                Operator::Greater => a > b,
+                _ => a >= b,
+            };
+            return (-i64::from(holds), Type::Word);
+        }
+
+        if b == 0 && matches!(op, Operator::Slash | Operator::Mod) {
+            self.abort(format!(
+                "Division by zero in {} {} {}",
+                left.0,
+                Token::Operator(op),
+                right.0
+            ));
+        }
+        if !(0..64).contains(&b) && matches!(op, Operator::ShiftLeft | Operator::ShiftRight) {
+            self.abort(format!("Shift count {} is out of range", b));
+        }
+        let value = match op {
+            Operator::Star => a * b,
+            Operator::Slash => a / b,
+            Operator::Mod => a % b,
+            Operator::Plus => a + b,
+            Operator::Minus => a - b,
+            Operator::ShiftLeft => {
+                (i128::from(a) << b).clamp(i64::MIN.into(), i64::MAX.into()) as i64
+            }
+            Operator::ShiftRight => a >> b,
+            Operator::Ampersand => a & b,
+            Operator::Caret => a ^ b,
+            Operator::Bar => a | b,
+            _ => unreachable!("{:?} is not a binary operator", op),
+        };
+        if !Self::in_range(&ty, value) {
+            self.abort(format!(
+                "{} {} {} overflows a {}",
+                left.0,
+                Token::Operator(op),
+                right.0,
+                ty
+            ));
+        }
+        (value, ty)
+    }
+
+    /// A value as the signed number a register of type `ty` holds.
+    fn signed(ty: &Type, value: i64) -> i64 {
+        let unused = 64 - 8 * ty.bytes() as u32;
+        (value << unused) >> unused
+    }
+
+    /// Applies a binary operator to the left operand on the stack and the
+    /// right one in D0, leaving the result in D0 (or A0 for a pointer).
+    fn operation(&mut self, op: Operator, left: &Type, right: &Type) -> Type {
+        if let Some(code) = Self::condition_code(op) {
+            return self.relation(left, right, code);
+        }
+        match op {
+            Operator::Plus if left.is_pointer() => return self.offset_pointer(left, right, false),
+            Operator::Minus if left.is_pointer() => return self.offset_pointer(left, right, true),
+            _ => (),
+        }
+
+        let ty = self.pop_operands(left, right);
+        let suffix = ty.suffix();
+        match op {
+            Operator::Star if ty == Type::Long => self.call_runtime("RT_MULS"),
+            Operator::Star => self.emit_line(format!("MULS{} D7,D0", suffix)),
+            Operator::Slash => {
+                self.divide(&ty);
+                self.emit_line(format!("MOVE{} D7,D0", suffix));
+            }
+            Operator::Mod => self.remainder(&ty),
+            Operator::Plus => self.emit_line(format!("ADD{} D7,D0", suffix)),
+            Operator::Minus => {
+                self.emit_line(format!("SUB{} D7,D0", suffix));
+                self.emit_line(format!("NEG{} D0", suffix));
+            }
+            Operator::ShiftLeft | Operator::ShiftRight => {
+                let shift = if op == Operator::ShiftLeft {
+                    "ASL"
+                } else {
+                    "ASR"
+                };
+                self.emit_line(format!("{}{} D0,D7", shift, suffix));
+                self.emit_line(format!("MOVE{} D7,D0", suffix));
+            }
+            Operator::Ampersand => self.emit_line(format!("AND{} D7,D0", suffix)),
+            Operator::Caret => self.emit_line(format!("EOR{} D7,D0", suffix)),
+            Operator::Bar => self.emit_line(format!("OR{} D7,D0", suffix)),
+            _ => unreachable!("{:?} is not a binary operator", op),
+        }
+        ty
+    }
+
+    /// The condition code that tests a comparison. CMP subtracts D7 from
+    /// D0, so the conditions for `<` and `>` are swapped.
+    fn condition_code(op: Operator) -> Option<&'static str> {
+        match op {
+            Operator::Equals => Some("EQ"),
+            Operator::NotEquals => Some("NE"),
+            Operator::Less => Some("GT"),
+            Operator::LessEquals => Some("GE"),
+            Operator::Greater => Some("LT"),
+            Operator::GreaterEquals => Some("LE"),
+            _ => None,
+        }
+    }
+
+    /// The condition code that holds when `code` does not.
+    fn inverse(code: &str) -> &'static str {
+        match code {
+            "EQ" => "NE",
+            "NE" => "EQ",
+            "GT" => "LE",
+            "LE" => "GT",
+            "LT" => "GE",
+            _ => "LT",
+        }
+    }
+
+    /// Compares the left operand on the stack with the right one. D0 is set
+    /// to -1 if the condition `code` holds and 0 otherwise, unless the
+    /// comparison is only tested.
+    fn relation(&mut self, left: &Type, right: &Type, code: &'static str) -> Type {
+        if left.is_pointer() || right.is_pointer() {
+            return self.compare_pointers(left, right, code);
+        }
+        let ty = self.pop_operands(left, right);
+        self.emit_line(format!("CMP{} D7,D0", ty.suffix()));
+        self.compare = Some(code);
+        Type::Word
+    }
+
+    /// Compares the pointer on the stack with the one in A0. Either can be
+    /// `nil`; otherwise they must point to the same type.
+    fn compare_pointers(&mut self, left: &Type, right: &Type, code: &'static str) -> Type {
+        let nil = Type::pointer_to(Type::Void);
+        let compatible = left == right || *left == nil || *right == nil;
+        if !left.is_pointer() || !right.is_pointer() || !compatible {
+            self.abort(format!(
+                "Type mismatch: cannot compare {} with {}",
+                left, right
+            ));
+        }
+        if code != "EQ" && code != "NE" {
+            self.abort(String::from("Pointers can only be compared with = and <>"));
+        }
+        self.emit_line(String::from("MOVE.L (SP)+,D7"));
+        self.emit_line(String::from("CMPA.L D7,A0"));
+        self.compare = Some(code);
+        Type::Word
+    }
+
+    fn bool_factor(&mut self) -> Type {
+        match self.lookahead {
+            Token::Keyword(Keyword::True) => {
+                self.match_keyword(Keyword::True);
+                self.load_constant(-1, Type::Word);
+                Type::Word
+            }
+            Token::Keyword(Keyword::False) => {
+                self.match_keyword(Keyword::False);
+                self.load_constant(0, Type::Word);
+                Type::Word
+            }
+            _ => self.binary(0),
+        }
+    }
+
+    /// Reads a condition joined by `or`, which binds most loosely.
+    fn or_condition(&mut self) -> Condition {
+        let mut terms = vec![self.xor_condition()];
+        while self.lookahead == Token::Keyword(Keyword::Or) {
+            self.match_keyword(Keyword::Or);
+            terms.push(self.xor_condition());
+        }
+        match terms.len() {
+            1 => terms.pop().unwrap(),
+            _ => Condition::Or(terms),
+        }
+    }
+
+    /// Reads operands joined by `xor`. Both sides of an `xor` decide its
+    /// result, so it is computed as a value with EOR and tested as a whole.
+    fn xor_condition(&mut self) -> Condition {
+        let condition = self.and_condition();
+        if self.lookahead != Token::Keyword(Keyword::Xor) {
+            return condition;
+        }
+        self.begin_buffer();
+        let mut ty = self.materialize(condition);
+        while self.lookahead == Token::Keyword(Keyword::Xor) {
+            self.push(&ty);
+            self.match_keyword(Keyword::Xor);
+            let right = self.and_condition();
+            let right = self.materialize(right);
+            ty = self.pop_operands(&ty, &right);
+            self.emit_line(format!("EOR{} D7,D0", ty.suffix()));
+        }
+        Condition::Test(self.end_buffer(), ty)
+    }
+
+    fn and_condition(&mut self) -> Condition {
+        let mut factors = vec![self.not_condition()];
+        while self.lookahead == Token::Keyword(Keyword::And) {
+            self.match_keyword(Keyword::And);
+            factors.push(self.not_condition());
+        }
+        match factors.len() {
+            1 => factors.pop().unwrap(),
+            _ => Condition::And(factors),
+        }
+    }
+
+    /// Reads a test, possibly behind `not`, holding back its code.
+    fn not_condition(&mut self) -> Condition {
+        if self.lookahead == Token::Keyword(Keyword::Not) {
+            self.match_keyword(Keyword::Not);
+            return Condition::Not(Box::new(self.not_condition()));
+        }
+        self.begin_buffer();
+        let ty = self.bool_factor();
+        if self.buffers.last().unwrap().is_empty() {
+            if let Some((value, ty)) = self.pending.take() {
+                self.buffers.pop();
+                return Condition::Constant(value, ty);
+            }
+        }
+        if let Some(code) = self.compare.take() {
+            return Condition::Compare(self.end_buffer(), code);
+        }
+        Condition::Test(self.end_buffer(), ty)
     }
 
-    fn abort(&mut self, message: String) {
-        self.error(message);
-        panic!("^^^^^^^");
-    }
+    /// Emits code that branches to `target` when `condition` is `sense` and
+    /// falls through otherwise. An `and` or `or` stops at the first operand
+    /// that decides it, so the rest are never computed.
+    fn branch(&mut self, condition: Condition, sense: bool, target: &str) {
+        match condition {
+            Condition::Test(code, ty) => {
+                self.emit_lines(code);
+                if ty.is_pointer() {
+                    self.emit_line(String::from("MOVE.L A0,D0"));
+                }
+                self.emit_line(format!("TST{} D0", ty.suffix()));
+                let op = if sense { "BNE" } else { "BEQ" };
+                self.emit_line(format!("{} {}", op, target));
+            }
+            Condition::Compare(code, test) => {
+                self.emit_lines(code);
+                let test = if sense { test } else { Self::inverse(test) };
+                self.emit_line(format!("B{} {}", test, target));
+            }
+            Condition::Not(condition) => self.branch(*condition, !sense, target),
+            Condition::Constant(value, _) => {
+                if (value != 0) == sense {
+                    self.emit_line(format!("BRA {}", target));
+                }
+            }
+            Condition::And(operands) => self.branch_chain(operands, false, sense, target),
+            Condition::Or(operands) => self.branch_chain(operands, true, sense, target),
+        }
+    }
+
+    /// Branches for the operands of an `and` (`decides` false) or an `or`
+    /// (`decides` true), where any operand with the value `decides` settles
+    /// the result.
+    fn branch_chain(
+        &mut self,
+        mut operands: Vec<Condition>,
+        decides: bool,
+        sense: bool,
+        target: &str,
+    ) {
+        if sense == decides {
+            for operand in operands {
+                self.branch(operand, sense, target);
+            }
+            return;
+        }
+        let last = operands.pop().unwrap();
+        let skip = self.new_label();
+        for operand in operands {
+            self.branch(operand, decides, &skip);
+        }
+        self.branch(last, sense, target);
+        self.post_label(&skip);
+    }
+
+    /// Computes a condition as a value in D0. A lone test keeps its own
+    /// value; `not`, `and` and `or` give -1 or 0.
+    fn materialize(&mut self, condition: Condition) -> Type {
+        match condition {
+            Condition::Test(code, ty) => {
+                self.emit_lines(code);
+                ty
+            }
+            Condition::Compare(code, test) => {
+                self.emit_lines(code);
+                self.compare = Some(test);
+                Type::Word
+            }
+            Condition::Not(condition) => self.truth_value(*condition, false),
+            Condition::Constant(value, ty) => {
+                self.load_constant(value, ty.clone());
+                ty
+            }
+            condition => self.truth_value(condition, true),
+        }
+    }
+
+    /// Loads -1 into D0 if `condition` is `sense`, 0 otherwise.
+    fn truth_value(&mut self, condition: Condition, sense: bool) -> Type {
+        if let Condition::Constant(value, _) = condition {
+            let truth = (value != 0) == sense;
+            self.load_constant(if truth { -1 } else { 0 }, Type::Word);
+            return Type::Word;
+        }
+        let false_label = self.new_label();
+        let done = self.new_label();
+        self.branch(condition, !sense, &false_label);
+        self.emit_line(String::from("MOVE.W #-1,D0"));
+        self.emit_line(format!("BRA {}", done));
+        self.post_label(&false_label);
+        self.emit_line(String::from("CLR.W D0"));
+        self.post_label(&done);
+        Type::Word
+    }
+
+    /// Compiles a boolean expression and loads its value into D0, for
+    /// tests.
+    #[cfg(test)]
+    pub fn bool_expression(&mut self) -> Type {
+        let ty = self.boolean();
+        self.flush();
+        ty
+    }
+
+    /// Compiles a boolean expression, leaving a constant result pending.
+    fn boolean(&mut self) -> Type {
+        let condition = self.choice_condition();
+        self.materialize(condition)
+    }
+
+    /// Reads a condition, which may go on to choose between two values with
+    /// `c ? a : b`.
+    fn choice_condition(&mut self) -> Condition {
+        let condition = self.or_condition();
+        if !self.is_punct(Punct::Question) {
+            return condition;
+        }
+        self.match_punct(Punct::Question);
+        self.begin_buffer();
+        let ty = self.choose(condition, Token::Punct(Punct::Colon));
+        Condition::Test(self.end_buffer(), ty)
+    }
+
+    /// Compiles `if c then a else b` as a factor.
+    fn conditional(&mut self) -> Type {
+        self.match_keyword(Keyword::If);
+        let condition = self.or_condition();
+        self.match_keyword(Keyword::Then);
+        self.choose(condition, Token::Keyword(Keyword::Else))
+    }
+
+    /// Compiles the two values of a conditional expression, separated by
+    /// `separator`, and leaves the one `condition` picks in D0 (or A0 for
+    /// pointers). Their code is held back so that both can be widened to
+    /// the type of the result.
+    fn choose(&mut self, condition: Condition, separator: Token) -> Type {
+        self.begin_buffer();
+        let first = self.boolean();
+        let first_code = self.end_buffer();
+        self.match_token(separator);
+        self.begin_buffer();
+        let second = self.boolean();
+        let second_code = self.end_buffer();
+
+        let nil = Type::pointer_to(Type::Void);
+        let ty = match (&first, &second) {
+            (Type::Pointer(_), Type::Pointer(_)) if first == second || second == nil => {
+                first.clone()
+            }
+            (Type::Pointer(_), Type::Pointer(_)) if first == nil => second.clone(),
+            _ if first.is_scalar() && second.is_scalar() => first.clone().max(second.clone()),
+            _ => self.abort(format!(
+                "Type mismatch: cannot choose between {} and {}",
+                first, second
+            )),
+        };
+
+        let otherwise = self.new_label();
+        let done = self.new_label();
+        self.branch(condition, false, &otherwise);
+        self.emit_lines(first_code);
+        self.convert(&first, &ty, "D0");
+        self.emit_line(format!("BRA {}", done));
+        self.post_label(&otherwise);
+        self.emit_lines(second_code);
+        self.convert(&second, &ty, "D0");
+        self.post_label(&done);
+        ty
+    }
+
+    /// Compiles a lone assignment; statements reach `assign` through
+    /// `named_statement` once the name has been read.
+    #[cfg(test)]
+    pub fn assignment(&mut self) {
+        let name = self.get_name();
+        self.assign(&name);
+    }
+
+    /// Assigns to a variable, an array element or a field. An address
+    /// computed into A0 is kept on the stack while the value is computed.
+    fn assign(&mut self, name: &str) {
+        let symbol = self.lookup_target(name);
+        let (location, ty) = self.designator(&symbol);
+        self.check_scalar(name, &ty);
+        self.assign_to(&location, &ty, name);
+    }
+
+    /// Compiles `*p = value`.
+    fn assign_through_pointer(&mut self) {
+        let ty = self.pointer_target();
+        self.assign_to(&Location::Indirect(0), &ty, "pointer target");
+    }
+
+    /// Compiles the `= value` of an assignment to a location that has been
+    /// reached.
+    fn assign_to(&mut self, location: &Location, ty: &Type, target: &str) {
+        let indirect = matches!(location, Location::Indirect(_));
+        if indirect {
+            self.emit_line(String::from("MOVE.L A0,-(SP)"));
+        }
+        self.match_operator(Operator::Equals);
+        let from = self.boolean();
+        self.coerce(&from, ty, target);
+        if indirect {
+            self.emit_line(String::from("MOVE.L (SP)+,A0"));
+        }
+        self.store(location, ty);
+    }
+
+    /// Reads a variable into D0, or a pointer into A0.
+    fn load(&mut self, location: &Location, ty: &Type) {
+        let register = Self::register(ty);
+        if let Location::Immediate(value) = location {
+            self.load_constant(*value, ty.clone());
+        } else if let Location::Reference(_) = location {
+            self.address(location);
+            self.emit_line(format!("MOVE{} (A0),{}", ty.suffix(), register));
+        } else {
+            let operand = self.operand(location);
+            self.emit_line(format!("MOVE{} {},{}", ty.suffix(), operand, register));
+        }
+    }
+
+    /// Writes D0 to a variable. PC-relative operands cannot be written to,
+    /// so globals go through A0.
+    fn store(&mut self, location: &Location, ty: &Type) {
+        if let Location::Frame(_) | Location::Indirect(_) = location {
+            let operand = self.operand(location);
+            self.emit_line(format!("MOVE{} D0,{}", ty.suffix(), operand));
+        } else {
+            self.address(location);
+            self.emit_line(format!("MOVE{} D0,(A0)", ty.suffix()));
+        }
+    }
+
+    /// Loads the address of a variable into A0.
+    fn address(&mut self, location: &Location) {
+        let operand = self.operand(location);
+        match location {
+            Location::Reference(_) => self.emit_line(format!("MOVE.L {},A0", operand)),
+            Location::Indirect(0) => (),
+            _ => self.emit_line(format!("LEA {},A0", operand)),
+        }
+    }
+
+    /// Pushes the address of a variable, for a reference parameter.
+    fn push_address(&mut self, location: &Location) {
+        let operand = self.operand(location);
+        match location {
+            Location::Reference(_) => self.emit_line(format!("MOVE.L {},-(SP)", operand)),
+            _ => self.emit_line(format!("PEA {}", operand)),
+        }
+    }
+
+    /// Finds the loop a `break` or `continue` refers to: the innermost one,
+    /// or the one carrying the given name. Also returns how many bytes the
+    /// loops being left have on the stack.
+    fn find_loop(&mut self, loops: &[LoopLabels], keyword: &str) -> (LoopLabels, usize) {
+        let name = match self.lookahead {
+            Token::Ident(_) => Some(self.get_name()),
+            _ => None,
+        };
+
+        let mut stack = 0;
+        for labels in loops.iter().rev() {
+            if name.is_none() || labels.name == name {
+                return (labels.clone(), stack);
+            }
+            stack += labels.stack;
+        }
+
+        match name {
+            Some(name) => self.abort(format!("No enclosing loop named {}", name)),
+            None => self.abort(format!("{} outside of a loop", keyword)),
+        }
+    }
+
+    fn pop_stack(&mut self, bytes: usize) {
+        match bytes {
+            0 => (),
+            1..=8 => self.emit_line(format!("ADDQ #{},SP", bytes)),
+            _ => self.emit_line(format!("ADD #{},SP", bytes)),
+        }
+    }
+
+    fn do_break(&mut self, loops: &[LoopLabels]) {
+        self.match_keyword(Keyword::Break);
+        let (labels, stack) = self.find_loop(loops, "break");
+        self.pop_stack(stack + labels.stack);
+        self.emit_line(format!("BRA {}", labels.exit));
+    }
+
+    fn do_continue(&mut self, loops: &[LoopLabels]) {
+        self.match_keyword(Keyword::Continue);
+        let (labels, stack) = self.find_loop(loops, "continue");
+        self.pop_stack(stack);
+        self.emit_line(format!("BRA {}", labels.next));
+    }
+
+    /// Pushes the arguments of a call from left to right, calls the
+    /// procedure, then pops the arguments again.
+    fn call(&mut self, symbol: &Symbol) {
+        self.match_punct(Punct::LParen);
+        let mut bytes = 0;
+        for (i, param) in symbol.params.iter().enumerate() {
+            if self.is_punct(Punct::RParen) {
+                self.abort_arguments(symbol);
+            }
+            if i > 0 {
+                self.match_punct(Punct::Comma);
+            }
+            let target = format!("argument {} of {}", i + 1, symbol.name);
+            match param.passing {
+                Passing::Value => {
+                    let ty = self.boolean();
+                    self.coerce(&ty, &param.ty, &target);
+                    self.push(&param.ty);
+                }
+                Passing::Reference if matches!(self.lookahead, Token::Str(_)) => {
+                    if param.ty != Type::Byte {
+                        self.abort(format!(
+                            "Type mismatch: cannot pass a string as var {} {}",
+                            param.ty, target
+                        ));
+                    }
+                    let label = self.get_string();
+                    self.emit_line(format!("PEA {}(PC)", label));
+                }
+                Passing::Reference => {
+                    let name = self.get_name();
+                    let variable = self.lookup_target(&name);
+                    let (location, ty) = self.designator(&variable);
+                    self.push_address(&location);
+                    if ty != param.ty {
+                        self.abort(format!(
+                            "Type mismatch: cannot pass {} {} as var {} {}",
+                            ty, name, param.ty, target
+                        ));
+                    }
+                }
+            }
+            bytes += param.bytes();
+        }
+        if !self.is_punct(Punct::RParen) {
+            self.abort_arguments(symbol);
+        }
+        self.match_punct(Punct::RParen);
+
+        let operand = self.operand(&symbol.location);
+        self.emit_line(format!("BSR {}", operand));
+        self.pop_stack(bytes);
+    }
+
+    fn abort_arguments(&mut self, symbol: &Symbol) -> ! {
+        self.abort(format!(
+            "{} takes {} argument(s)",
+            symbol.name,
+            symbol.params.len()
+        ));
+    }
+
+    /// Compiles a statement that starts with a name: a declaration using a
+    /// record type, an assignment, a procedure call, or a loop labelled with
+    /// `name:`.
+    fn named_statement(&mut self, loops: &[LoopLabels]) {
+        let name = self.get_name();
+        match self.lookahead {
+            Token::Ident(_) | Token::Operator(Operator::Star) => {
+                let ty = self.named_type(&name);
+                self.decl_list(ty);
+                return;
+            }
+            Token::Punct(Punct::Colon) => {
+                self.match_punct(Punct::Colon);
+                let name = Some(name);
+                match self.lookahead {
+                    Token::Keyword(Keyword::While) => self.do_while(loops, name),
+                    Token::Keyword(Keyword::Loop) => self.do_loop(loops, name),
+                    Token::Keyword(Keyword::Repeat) => self.do_repeat(loops, name),
+                    Token::Keyword(Keyword::For) => self.do_for(loops, name),
+                    Token::Keyword(Keyword::Do) => self.do_do(loops, name),
+                    _ => self.expected(String::from("Loop")),
+                }
+                return;
+            }
+            Token::Punct(Punct::LParen) => {
+                let symbol = self.lookup_procedure(&name);
+                self.call(&symbol);
+            }
+            _ => self.assign(&name),
+        }
+        self.match_punct(Punct::Semicolon);
+    }
+
+    /// Compiles one statement, choosing the construct from its first token.
+    /// Compound statements end with a block; the rest need a `;`.
+    pub fn statement(&mut self, loops: &[LoopLabels]) {
+        match self.lookahead {
+            Token::Keyword(Keyword::Var)
+            | Token::Keyword(Keyword::Byte)
+            | Token::Keyword(Keyword::Word)
+            | Token::Keyword(Keyword::Long) => self.decl(),
+            Token::Keyword(Keyword::Const) => self.do_const(),
+            Token::Keyword(Keyword::Type) => self.do_type(),
+            Token::Keyword(Keyword::Enum) => self.do_enum(),
+            Token::Keyword(Keyword::Proc) => self.do_proc(),
+            Token::Keyword(Keyword::Func) => self.do_func(),
+            Token::Keyword(Keyword::Return) => {
+                self.do_return();
+                self.match_punct(Punct::Semicolon);
+            }
+            Token::Keyword(Keyword::If) => self.do_if(loops),
+            Token::Keyword(Keyword::Case) => self.do_case(loops),
+            Token::Keyword(Keyword::While) => self.do_while(loops, None),
+            Token::Keyword(Keyword::Loop) => self.do_loop(loops, None),
+            Token::Keyword(Keyword::Repeat) => self.do_repeat(loops, None),
+            Token::Keyword(Keyword::For) => self.do_for(loops, None),
+            Token::Keyword(Keyword::Do) => self.do_do(loops, None),
+            Token::Keyword(Keyword::Break) => {
+                self.do_break(loops);
+                self.match_punct(Punct::Semicolon);
+            }
+            Token::Keyword(Keyword::Continue) => {
+                self.do_continue(loops);
+                self.match_punct(Punct::Semicolon);
+            }
+            Token::Ident(_) => self.named_statement(loops),
+            Token::Operator(Operator::Star) => {
+                self.assign_through_pointer();
+                self.match_punct(Punct::Semicolon);
+            }
+            Token::Punct(Punct::LBrace) => self.block(loops),
+            Token::Punct(Punct::Semicolon) => self.match_punct(Punct::Semicolon),
+            _ => self.expected(String::from("Statement")),
+        }
+    }
+
+    /// Compiles a braced list of statements. Names declared inside it go
+    /// out of scope at the closing brace, and their frame slots can be
+    /// reused by the blocks that follow.
+    pub fn block(&mut self, loops: &[LoopLabels]) {
+        self.match_punct(Punct::LBrace);
+        self.symbols.enter_scope();
+        let frame = self.frame;
+        while !self.is_punct(Punct::RBrace) {
+            self.statement(loops);
+        }
+        self.frame = frame;
+        self.symbols.leave_scope();
+        self.match_punct(Punct::RBrace);
+    }
+
+    /// Starts a new stack frame, holding back its code until the frame size
+    /// is known. Returns the state of the enclosing frame.
+    fn begin_frame(&mut self) -> (usize, usize) {
+        let outer = (self.frame, self.frame_size);
+        self.frame = 0;
+        self.frame_size = 0;
+        self.begin_buffer();
+        outer
+    }
+
+    /// Ends the current frame, returning its code and size.
+    fn end_frame(&mut self, outer: (usize, usize)) -> (Vec<String>, usize) {
+        let code = self.end_buffer();
+        let size = self.frame_size;
+        (self.frame, self.frame_size) = outer;
+        (code, size)
+    }
+
+    fn emit_lines(&mut self, lines: Vec<String>) {
+        for line in lines {
+            self.emit_line(line);
+        }
+    }
+
+    /// Compiles a loop body with `labels` added as the innermost loop.
+    fn loop_block(&mut self, loops: &[LoopLabels], labels: LoopLabels) {
+        let mut loops = loops.to_vec();
+        loops.push(labels);
+        self.block(&loops);
+    }
+
+    /// Adds a name to the current scope, which must not already hold it.
+    fn declare(&mut self, symbol: Symbol) {
+        let name = symbol.name.clone();
+        if let Err(first) = self.symbols.declare(symbol) {
+            self.abort(format!(
+                "Duplicate name {}, first declared at line {}",
+                name, first.line
+            ));
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Symbol {
+        match self.symbols.lookup(name) {
+            Some(symbol) => symbol.clone(),
+            None => self.abort(format!("Undeclared name {}", name)),
+        }
+    }
+
+    /// Resolves a name that is read for its value.
+    fn lookup_value(&mut self, name: &str) -> Symbol {
+        let symbol = self.lookup(name);
+        if let SymbolKind::Procedure | SymbolKind::Type = symbol.kind {
+            self.abort(format!("{} is a {}, not a variable", name, symbol.kind));
+        }
+        symbol
+    }
+
+    /// Resolves a name that is called.
+    fn lookup_procedure(&mut self, name: &str) -> Symbol {
+        let symbol = self.lookup(name);
+        if symbol.kind != SymbolKind::Procedure {
+            self.abort(format!("{} is a {}, not a procedure", name, symbol.kind));
+        }
+        symbol
+    }
+
+    /// Resolves a name that is assigned to.
+    fn lookup_target(&mut self, name: &str) -> Symbol {
+        let symbol = self.lookup(name);
+        match symbol.kind {
+            SymbolKind::Variable | SymbolKind::Parameter => symbol,
+            kind => self.abort(format!("Cannot assign to {} {}", kind, name)),
+        }
+    }
+
+    /// The addressing mode that reaches a stored value.
+    fn operand(&self, location: &Location) -> String {
+        match location {
+            Location::Global(label) => format!("{}(PC)", label),
+            Location::Frame(offset) | Location::Reference(offset) => {
+                format!("{}(A6)", offset)
+            }
+            Location::Immediate(value) => format!("#{}", value),
+            Location::Indirect(0) => String::from("(A0)"),
+            Location::Indirect(offset) => format!("{}(A0)", offset),
+            Location::Code(label) => label.clone(),
+            Location::None => unreachable!("a type has no storage"),
+        }
+    }
+
+    /// Reads the `*`s that make a declared name a pointer.
+    fn pointers(&mut self, mut ty: Type) -> Type {
+        while self.lookahead == Token::Operator(Operator::Star) {
+            self.match_operator(Operator::Star);
+            ty = Type::pointer_to(ty);
+        }
+        ty
+    }
+
+    /// Reads the `[length]` that makes a declaration an array, if it is
+    /// next.
+    fn dimension(&mut self, ty: Type) -> Type {
+        if !self.is_punct(Punct::LBracket) {
+            return ty;
+        }
+        self.match_punct(Punct::LBracket);
+        let length = self.constant();
+        if !(1..=0x7FFF).contains(&length) {
+            self.abort(format!("Array length {} is out of range", length));
+        }
+        self.match_punct(Punct::RBracket);
+        Type::Array(Box::new(ty), length as usize)
+    }
+
+    /// Reads a value known while compiling: a literal or the name of a
+    /// constant, possibly negated, or a character.
+    fn constant(&mut self) -> i64 {
+        match self.lookahead {
+            Token::Operator(Operator::Minus) => {
+                self.match_operator(Operator::Minus);
+                -self.constant()
+            }
+            Token::Char(c) => {
+                self.next();
+                i64::from(c)
+            }
+            Token::Ident(_) => {
+                let name = self.get_name();
+                match self.lookup(&name).location {
+                    Location::Immediate(value) => value,
+                    _ => self.abort(format!("{} is not a constant", name)),
+                }
+            }
+            _ => self.get_num(),
+        }
+    }
+
+    /// Compiles `const N = 10, M = -N;`. Constants take no storage; their
+    /// values are used as immediates.
+    fn do_const(&mut self) {
+        self.match_keyword(Keyword::Const);
+        loop {
+            let span = self.span;
+            let name = self.get_name();
+            self.match_operator(Operator::Equals);
+            let value = self.constant();
+            if !Type::Long.fits(value) {
+                self.abort(format!("Constant {} does not fit in a long", value));
+            }
+            let ty = Self::literal_type(value);
+            let location = Location::Immediate(value);
+            let symbol = Symbol::new(&name, SymbolKind::Constant, ty, location, span);
+            self.declare(symbol);
+            if !self.is_punct(Punct::Comma) {
+                break;
+            }
+            self.match_punct(Punct::Comma);
+        }
+        self.match_punct(Punct::Semicolon);
+    }
+
+    /// Compiles `enum Color { Red, Green = 5, Blue }`. The members become
+    /// constants numbered on from 0 or from the last given value, and the
+    /// name a word type for variables that hold them.
+    fn do_enum(&mut self) {
+        self.match_keyword(Keyword::Enum);
+        let span = self.span;
+        let name = self.get_name();
+        self.declare(Symbol::new(
+            &name,
+            SymbolKind::Type,
+            Type::Word,
+            Location::None,
+            span,
+        ));
+        self.match_punct(Punct::LBrace);
+        let mut value = 0;
+        loop {
+            let span = self.span;
+            let member = self.get_name();
+            if self.lookahead == Token::Operator(Operator::Equals) {
+                self.match_operator(Operator::Equals);
+                value = self.constant();
+            }
+            if !Self::in_range(&Type::Word, value) {
+                self.abort(format!("Enum value {} does not fit in a word", value));
+            }
+            let ty = Self::literal_type(value);
+            let location = Location::Immediate(value);
+            self.declare(Symbol::new(
+                &member,
+                SymbolKind::Constant,
+                ty,
+                location,
+                span,
+            ));
+            value += 1;
+            if !self.is_punct(Punct::Comma) {
+                break;
+            }
+            self.match_punct(Punct::Comma);
+        }
+        self.match_punct(Punct::RBrace);
+    }
+
+    /// Reads an optional initial value for a global, which must be known
+    /// while compiling.
+    fn initializer(&mut self, ty: &Type) -> Option<i64> {
+        if self.lookahead != Token::Operator(Operator::Equals) {
+            return None;
+        }
+
+        if !ty.is_scalar() {
+            self.abort(format!("A {} cannot have an initial value", ty));
+        }
+        self.match_operator(Operator::Equals);
+        let value = self.constant();
+        if !ty.fits(value) {
+            self.abort(format!("Initial value {} does not fit in a {}", value, ty));
+        }
+        Some(value)
+    }
+
+    fn declare_variable(&mut self, name: &str, ty: Type, location: Location, span: Span) {
+        let symbol = Symbol::new(name, SymbolKind::Variable, ty, location, span);
+        if let Err(first) = self.symbols.declare(symbol) {
+            self.abort(format!(
+                "Duplicate variable name {}, first declared at line {}",
+                name, first.line
+            ));
+        }
+    }
+
+    /// Declares a variable. Globals get storage in the data section;
+    /// anything declared in a block gets a slot in the stack frame below A6,
+    /// set by its initializer each time the declaration runs. Slots are kept
+    /// even so words and longs stay aligned.
+    fn alloc(&mut self, ty: Type) {
+        let ty = self.pointers(ty);
+        let span = self.span;
+        let name = self.get_name();
+        let ty = self.dimension(ty);
+        if self.symbols.is_global() {
+            let label = Self::label_for(&name);
+            self.declare_variable(&name, ty.clone(), Location::Global(label.clone()), span);
+            let value = self.initializer(&ty);
+            self.globals.push((label, ty, value));
+            return;
+        }
+
+        self.frame += (ty.bytes() + 1) & !1;
+        self.frame_size = self.frame_size.max(self.frame);
+        let location = Location::Frame(-(self.frame as i32));
+        if self.lookahead == Token::Operator(Operator::Equals) {
+            if ty.is_aggregate() {
+                self.abort(format!("A {} cannot have an initial value", ty));
+            }
+            self.match_operator(Operator::Equals);
+            let from = self.boolean();
+            self.coerce(&from, &ty, &name);
+            self.store(&location, &ty);
+        }
+        self.declare_variable(&name, ty, location, span);
+    }
+
+    /// Resolves a name used as a type.
+    fn named_type(&mut self, name: &str) -> Type {
+        let symbol = self.lookup(name);
+        if symbol.kind != SymbolKind::Type {
+            self.abort(format!("{} is a {}, not a type", name, symbol.kind));
+        }
+        symbol.ty
+    }
+
+    /// Reads the type a declaration starts with: `byte`, `word`, `long`,
+    /// `var` for a word, or the name of a record type.
+    fn decl_type(&mut self) -> Type {
+        if let Some(ty) = self.type_keyword() {
+            return ty;
+        }
+        match self.lookahead {
+            Token::Keyword(Keyword::Var) => {
+                self.match_keyword(Keyword::Var);
+                Type::Word
+            }
+            Token::Ident(_) => {
+                let name = self.get_name();
+                self.named_type(&name)
+            }
+            _ => self.expected(String::from("Type")),
+        }
+    }
+
+    fn decl(&mut self) {
+        let ty = self.decl_type();
+        self.decl_list(ty);
+    }
+
+    /// Compiles the names declared with `ty`, up to the closing `;`.
+    fn decl_list(&mut self, ty: Type) {
+        self.alloc(ty.clone());
+        while self.is_punct(Punct::Comma) {
+            self.match_punct(Punct::Comma);
+            self.alloc(ty.clone());
+        }
+        self.match_punct(Punct::Semicolon);
+    }
+
+    /// Reads one formal parameter: a name, preceded by `var` if it is passed
+    /// by reference and by its type if it is not a word. Arrays and records
+    /// can only be passed by reference.
+    fn formal_param(&mut self) -> (String, Param, Span) {
+        let passing = if self.lookahead == Token::Keyword(Keyword::Var) {
+            self.match_keyword(Keyword::Var);
+            Passing::Reference
+        } else {
+            Passing::Value
+        };
+        let (ty, span, name) = match self.type_keyword() {
+            Some(ty) => {
+                let ty = self.pointers(ty);
+                (ty, self.span, self.get_name())
+            }
+            None => {
+                let span = self.span;
+                let name = self.get_name();
+                match self.lookahead {
+                    Token::Ident(_) | Token::Operator(Operator::Star) => {
+                        let ty = self.named_type(&name);
+                        let ty = self.pointers(ty);
+                        (ty, self.span, self.get_name())
+                    }
+                    _ => (Type::Word, span, name),
+                }
+            }
+        };
+        let ty = self.dimension(ty);
+        if ty.is_aggregate() && passing == Passing::Value {
+            self.abort(format!(
+                "Parameter {} is a {} and must be passed by var",
+                name, ty
+            ));
+        }
+        (name, Param { passing, ty }, span)
+    }
+
+    fn formal_list(&mut self) -> Vec<(String, Param, Span)> {
+        self.match_punct(Punct::LParen);
+        let mut params = Vec::new();
+        if !self.is_punct(Punct::RParen) {
+            params.push(self.formal_param());
+            while self.is_punct(Punct::Comma) {
+                self.match_punct(Punct::Comma);
+                params.push(self.formal_param());
+            }
+        }
+        self.match_punct(Punct::RParen);
+        params
+    }
+
+    /// Declares the parameters in a new scope. Arguments are pushed from left
+    /// to right, so the last one sits just above the saved A6 and the return
+    /// address, at 8(A6).
+    fn declare_params(&mut self, params: &[(String, Param, Span)]) {
+        let mut offset = 8;
+        let mut locations = Vec::new();
+        for (_, param, _) in params.iter().rev() {
+            locations.push(match param.passing {
+                Passing::Value => Location::Frame(offset),
+                Passing::Reference => Location::Reference(offset),
+            });
+            offset += param.bytes() as i32;
+        }
+
+        self.symbols.enter_scope();
+        for ((name, param, span), location) in params.iter().zip(locations.into_iter().rev()) {
+            let ty = param.ty.clone();
+            let symbol = Symbol::new(name, SymbolKind::Parameter, ty, location, *span);
+            if self.symbols.declare(symbol).is_err() {
+                self.abort(format!("Duplicate parameter name {}", name));
+            }
+        }
+    }
+
+    /// Declares a record type, `type Name { word x, y; Other inner; }`, whose
+    /// fields are declared like variables. A field can point to the record
+    /// being declared.
+    fn do_type(&mut self) {
+        self.match_keyword(Keyword::Type);
+        let span = self.span;
+        let name = self.get_name();
+        self.match_punct(Punct::LBrace);
+        let mut fields: Vec<(String, Type)> = Vec::new();
+        while !self.is_punct(Punct::RBrace) {
+            let ty = if self.lookahead == Token::Ident(name.clone()) {
+                self.next();
+                Type::Named(name.clone())
+            } else {
+                self.decl_type()
+            };
+            loop {
+                let ty = self.pointers(ty.clone());
+                if let Type::Named(_) = ty {
+                    self.abort(format!("Record {} cannot contain itself", name));
+                }
+                let field = self.get_name();
+                if fields.iter().any(|(name, _)| *name == field) {
+                    self.abort(format!("Duplicate field name {}", field));
+                }
+                let ty = self.dimension(ty);
+                fields.push((field, ty));
+                if !self.is_punct(Punct::Comma) {
+                    break;
+                }
+                self.match_punct(Punct::Comma);
+            }
+            self.match_punct(Punct::Semicolon);
+        }
+        self.match_punct(Punct::RBrace);
+        if fields.is_empty() {
+            self.abort(format!("Record {} has no fields", name));
+        }
+
+        let ty = Type::Record(Box::new(Record::new(&name, fields)));
+        let symbol = Symbol::new(&name, SymbolKind::Type, ty, Location::None, span);
+        self.declare(symbol);
+    }
+
+    fn do_proc(&mut self) {
+        self.match_keyword(Keyword::Proc);
+        self.subroutine(Type::Void);
+    }
+
+    /// Compiles a function, whose result is a word unless a type follows
+    /// `func`.
+    fn do_func(&mut self) {
+        self.match_keyword(Keyword::Func);
+        let ty = self.type_keyword().unwrap_or(Type::Word);
+        self.subroutine(ty);
+    }
+
+    /// Compiles `name(a, var b) { ... }` after `proc` or `func` to a
+    /// subroutine with a frame built by LINK. The code is emitted where the
+    /// declaration stands, so a branch skips over it. A function that runs
+    /// off the end of its body returns 0.
+    fn subroutine(&mut self, ty: Type) {
+        if !self.symbols.is_global() {
+            self.abort(String::from("Procedures must be declared at the top level"));
+        }
+        let span = self.span;
+        let name = self.get_name();
+        let params = self.formal_list();
+
+        let label = Self::label_for(&name);
+        let location = Location::Code(label.clone());
+        let mut symbol = Symbol::new(&name, SymbolKind::Procedure, ty.clone(), location, span);
+        symbol.params = params.iter().map(|(_, param, _)| param.clone()).collect();
+        self.declare(symbol);
+
+        let skip = self.new_label();
+        let exit = self.new_label();
+        self.emit_line(format!("BRA {}", skip));
+        self.post_label(&label);
+        let outer = self.begin_frame();
+        self.declare_params(&params);
+        self.routine = Some((exit.clone(), ty.clone()));
+        self.block(&[]);
+        self.routine = None;
+        self.symbols.leave_scope();
+        let (code, size) = self.end_frame(outer);
+        self.emit_line(format!("LINK A6,#{}", -(size as i32)));
+        self.emit_lines(code);
+        if ty != Type::Void {
+            self.emit_line(format!("CLR{} D0", ty.suffix()));
+        }
+        self.post_label(&exit);
+        self.emit_line(String::from("UNLK A6"));
+        self.emit_line(String::from("RTS"));
+        self.post_label(&skip);
+    }
+
+    /// Leaves the current procedure through its epilogue, which also drops
+    /// anything loops have left on the stack. A function's result goes in
+    /// D0.
+    fn do_return(&mut self) {
+        self.match_keyword(Keyword::Return);
+        let (exit, ty) = match self.routine.clone() {
+            Some(routine) => routine,
+            None => self.abort(String::from("return outside of a procedure")),
+        };
+        if ty != Type::Void {
+            let from = self.boolean();
+            self.coerce(&from, &ty, "result");
+        }
+        self.emit_line(format!("BRA {}", exit));
+    }
+
+    /// Emits storage for every declared variable, after the code.
+    fn data_section(&mut self) {
+        for (label, ty, value) in self.globals.clone() {
+            // Records are laid out in words.
+            let count = match &ty {
+                Type::Array(element, length) if !element.is_aggregate() => *length,
+                Type::Array(..) | Type::Record(_) => ty.bytes() / 2,
+                _ => 1,
+            };
+            match value {
+                Some(value) => self.emit_line(format!("{}: DC{} {}", label, ty.suffix(), value)),
+                None => self.emit_line(format!("{}: DS{} {}", label, ty.suffix(), count)),
+            }
+        }
+    }
+
+    /// Emits the string pool, each string ending in a zero byte. Printable
+    /// runs are quoted, with `'` doubled; other bytes are written as numbers.
+    fn string_pool(&mut self) {
+        for (index, bytes) in self.strings.clone().into_iter().enumerate() {
+            let mut items = Vec::new();
+            let mut run = String::new();
+            for byte in bytes {
+                if (0x20..=0x7E).contains(&byte) {
+                    if byte == b'\'' {
+                        run.push('\'');
+                    }
+                    run.push(byte as char);
+                    continue;
+                }
+                if !run.is_empty() {
+                    items.push(format!("'{}'", run));
+                    run.clear();
+                }
+                items.push(byte.to_string());
+            }
+            if !run.is_empty() {
+                items.push(format!("'{}'", run));
+            }
+            items.push(String::from("0"));
+            self.emit_line(format!("S{:0>2}: DC.B {}", index, items.join(",")));
+        }
+    }
+
+    /// Compiles the whole program. The main code only gets a frame if its
+    /// blocks declare locals.
+    pub fn program(&mut self) {
+        let outer = self.begin_frame();
+        while self.lookahead != Token::Eof {
+            self.statement(&[]);
+        }
+        let (code, size) = self.end_frame(outer);
+        if size > 0 {
+            self.emit_line(format!("LINK A6,#{}", -(size as i32)));
+        }
+        self.emit_lines(code);
+        if size > 0 {
+            self.emit_line(String::from("UNLK A6"));
+        }
+        self.emit_line(String::from("RTS"));
+        for (name, code) in RUNTIME {
+            if self.runtime.contains(name) {
+                self.emit_lines(code.iter().map(|line| line.to_string()).collect());
+            }
+        }
+        self.data_section();
+        self.string_pool();
+    }
+
+    /// The label for a global variable or a procedure. Names cannot contain
+    /// `_`, so the prefix keeps them apart from generated labels and
+    /// registers.
+    fn label_for(name: &str) -> String {
+        format!("V_{}", name)
+    }
+
+    fn new_label(&mut self) -> String {
+        let label = format!("L{:0>2}", self.l_count);
+        self.l_count += 1;
+        label
+    }
+
+    fn post_label(&mut self, label: &str) {
+        self.emit_line(format!("{}:", label));
+    }
+
+    /// Compiles a condition that branches to `target` when it is false.
+    fn condition(&mut self, target: &str) {
+        let condition = self.choice_condition();
+        self.branch(condition, false, target);
+    }
+
+    fn do_if(&mut self, loops: &[LoopLabels]) {
+        self.match_keyword(Keyword::If);
+
+        let l1 = self.new_label();
+        let mut l2 = l1.clone();
+        self.condition(&l1);
+        self.block(loops);
+
+        if self.lookahead == Token::Keyword(Keyword::Else) {
+            self.match_keyword(Keyword::Else);
+            l2 = self.new_label();
+            self.emit_line(format!("BRA {}", l2));
+            self.post_label(&l1);
+            if self.lookahead == Token::Keyword(Keyword::If) {
+                self.do_if(loops);
+            } else {
+                self.block(loops);
+            }
+        }
+
+        self.post_label(&l2);
+    }
+
+    /// Compiles `case e { 1, 3..5: { ... } Red: { ... } else { ... } }`.
+    /// The arms are held back until all their labels are known, then
+    /// dispatched to from the value of `e`. Without an `else` arm a value
+    /// no arm holds does nothing.
+    fn do_case(&mut self, loops: &[LoopLabels]) {
+        self.match_keyword(Keyword::Case);
+        let from = self.binary(0);
+        self.check_number(&from);
+        let ty = from.clone().max(Type::Word);
+        self.convert(&from, &ty, "D0");
+        self.match_punct(Punct::LBrace);
+
+        let exit = self.new_label();
+        let mut default = exit.clone();
+        let mut arms: Vec<(Vec<(i64, i64)>, String)> = Vec::new();
+        self.begin_buffer();
+        while !self.is_punct(Punct::RBrace) {
+            if self.lookahead == Token::Keyword(Keyword::Else) {
+                self.match_keyword(Keyword::Else);
+                default = self.new_label();
+                self.post_label(&default);
+                self.block(loops);
+                break;
+            }
+            let ranges = self.case_labels(&ty, &arms);
+            self.match_punct(Punct::Colon);
+            let label = self.new_label();
+            self.post_label(&label);
+            self.block(loops);
+            self.emit_line(format!("BRA {}", exit));
+            arms.push((ranges, label));
+        }
+        self.match_punct(Punct::RBrace);
+        let code = self.end_buffer();
+
+        self.dispatch(&ty, &arms, &default);
+        self.emit_lines(code);
+        self.post_label(&exit);
+    }
+
+    /// Reads the values and `low..high` ranges that select an arm. Each must
+    /// fit the selector and be held by no other arm.
+    fn case_labels(&mut self, ty: &Type, arms: &[(Vec<(i64, i64)>, String)]) -> Vec<(i64, i64)> {
+        let mut ranges: Vec<(i64, i64)> = Vec::new();
+        loop {
+            let low = self.constant();
+            let high = if self.is_punct(Punct::Range) {
+                self.match_punct(Punct::Range);
+                self.constant()
+            } else {
+                low
+            };
+            for value in [low, high] {
+                if !Self::in_range(ty, value) {
+                    self.abort(format!("Case label {} does not fit in a {}", value, ty));
+                }
+            }
+            if low > high {
+                self.abort(format!("Case range {}..{} is empty", low, high));
+            }
+            let earlier = arms.iter().flat_map(|(ranges, _)| ranges).chain(&ranges);
+            if let Some((other, _)) = earlier.into_iter().find(|(l, h)| *l <= high && low <= *h) {
+                self.abort(format!("Duplicate case label {}", low.max(*other)));
+            }
+            ranges.push((low, high));
+            if !self.is_punct(Punct::Comma) {
+                break;
+            }
+            self.match_punct(Punct::Comma);
+        }
+        ranges
+    }
+
+    /// Whether a value fits a signed integer of type `ty`.
+    fn in_range(ty: &Type, value: i64) -> bool {
+        let bits = 8 * ty.bytes() as u32;
+        (-(1 << (bits - 1))..1 << (bits - 1)).contains(&value)
+    }
+
+    /// Branches from the selector in D0 to the arm whose labels hold it, or
+    /// to `default`. Labels that cover most of a small span are looked up
+    /// in a jump table; others are compared one at a time.
+    fn dispatch(&mut self, ty: &Type, arms: &[(Vec<(i64, i64)>, String)], default: &str) {
+        let ranges = || arms.iter().flat_map(|(ranges, _)| ranges);
+        let count = ranges().count() as i64;
+        if count >= JUMP_TABLE_MIN {
+            let low = ranges().map(|(low, _)| *low).min().unwrap();
+            let high = ranges().map(|(_, high)| *high).max().unwrap();
+            let span = high - low + 1;
+            if span <= 2 * count && span <= JUMP_TABLE_MAX {
+                self.jump_table(ty, arms, default, low, span);
+                return;
+            }
+        }
+
+        for (ranges, label) in arms {
+            for &(low, high) in ranges {
+                if low == high {
+                    self.emit_line(format!("CMP{} #{},D0", ty.suffix(), low));
+                    self.emit_line(format!("BEQ {}", label));
+                    continue;
+                }
+                let next = self.new_label();
+                self.emit_line(format!("CMP{} #{},D0", ty.suffix(), low));
+                self.emit_line(format!("BLT {}", next));
+                self.emit_line(format!("CMP{} #{},D0", ty.suffix(), high));
+                self.emit_line(format!("BLE {}", label));
+                self.post_label(&next);
+            }
+        }
+        self.emit_line(format!("BRA {}", default));
+    }
+
+    /// Jumps through a table of `span` arm labels indexed by the selector
+    /// less `low`. The unsigned compare sends values on either side of the
+    /// table to `default`.
+    fn jump_table(
+        &mut self,
+        ty: &Type,
+        arms: &[(Vec<(i64, i64)>, String)],
+        default: &str,
+        low: i64,
+        span: i64,
+    ) {
+        let mut targets = vec![default.to_string(); span as usize];
+        for (ranges, label) in arms {
+            for &(from, to) in ranges {
+                for value in from..=to {
+                    targets[(value - low) as usize] = label.clone();
+                }
+            }
+        }
+
+        let table = self.new_label();
+        if low != 0 {
+            self.emit_line(format!("SUB{} #{},D0", ty.suffix(), low));
+        }
+        self.emit_line(format!("CMP{} #{},D0", ty.suffix(), span - 1));
+        self.emit_line(format!("BHI {}", default));
+        self.emit_line(format!("LEA {}(PC),A0", table));
+        self.emit_line(String::from("ASL.W #2,D0"));
+        self.emit_line(String::from("MOVE.L 0(A0,D0.W),A0"));
+        self.emit_line(String::from("JMP (A0)"));
+        self.post_label(&table);
+        self.emit_line(format!("DC.L {}", targets.join(",")));
+    }
+
+    fn do_while(&mut self, loops: &[LoopLabels], name: Option<String>) {
+        self.match_keyword(Keyword::While);
+        let l1 = self.new_label();
+        let l2 = self.new_label();
+        self.post_label(&l1);
+        self.condition(&l2);
+        let labels = LoopLabels::new(name, &l2, &l1, 0);
+        self.loop_block(loops, labels);
+        self.emit_line(format!("BRA {}", l1));
+        self.post_label(&l2);
+    }
+
+    fn do_loop(&mut self, loops: &[LoopLabels], name: Option<String>) {
+        self.match_keyword(Keyword::Loop);
+        let l1 = self.new_label();
+        let l2 = self.new_label();
+        self.post_label(&l1);
+        let labels = LoopLabels::new(name, &l2, &l1, 0);
+        self.loop_block(loops, labels);
+        self.emit_line(format!("BRA {}", l1));
+        self.post_label(&l2);
+    }
+
+    fn do_repeat(&mut self, loops: &[LoopLabels], name: Option<String>) {
+        self.match_keyword(Keyword::Repeat);
+        let l1 = self.new_label();
+        let l2 = self.new_label();
+        let l3 = self.new_label();
+        self.post_label(&l1);
+        let labels = LoopLabels::new(name, &l3, &l2, 0);
+        self.loop_block(loops, labels);
+        self.match_keyword(Keyword::Until);
+        self.post_label(&l2);
+        self.condition(&l1);
+        self.post_label(&l3);
+    }
+
+    /// Counts the loop variable from the first bound up to the second. The
+    /// upper bound stays on the stack while the loop runs. The loop exits on
+    /// reaching the bound before incrementing, so a bound at the largest
+    /// value of the variable's type does not wrap around.
+    fn do_for(&mut self, loops: &[LoopLabels], name: Option<String>) {
+        self.match_keyword(Keyword::For);
+        let l1 = self.new_label();
+        let l2 = self.new_label();
+        let l3 = self.new_label();
+        let l4 = self.new_label();
+        let var = self.get_name();
+        let symbol = self.lookup_target(&var);
+        self.check_number(&symbol.ty);
+        let (location, ty) = (symbol.location, symbol.ty);
+        let suffix = ty.suffix();
+        let stack = ty.bytes().max(2);
+        self.match_operator(Operator::Equals);
+        let from = self.binary(0);
+        self.coerce(&from, &ty, &var);
+        self.store(&location, &ty);
+        self.match_keyword(Keyword::To);
+        let from = self.binary(0);
+        self.coerce(&from, &ty, &var);
+        self.push(&ty);
+        self.post_label(&l1);
+        self.address(&location);
+        self.emit_line(format!("MOVE{} (A0),D0", suffix));
+        self.emit_line(format!("CMP{} (SP),D0", suffix));
+        self.emit_line(format!("BGT {}", l2));
+        let labels = LoopLabels::new(name, &l3, &l4, stack);
+        self.loop_block(loops, labels);
+        self.post_label(&l4);
+        self.address(&location);
+        self.emit_line(format!("MOVE{} (A0),D0", suffix));
+        self.emit_line(format!("CMP{} (SP),D0", suffix));
+        self.emit_line(format!("BEQ {}", l2));
+        self.emit_line(format!("ADDQ{} #1,D0", suffix));
+        self.emit_line(format!("MOVE{} D0,(A0)", suffix));
+        self.emit_line(format!("BRA {}", l1));
+        self.post_label(&l2);
+        self.pop_stack(stack);
+        self.post_label(&l3);
+    }
+
+    /// Runs the block a fixed number of times using DBRA, skipping it
+    /// entirely when the count is zero or negative. DBRA counts in a word,
+    /// which is kept on the stack while the block runs.
+    fn do_do(&mut self, loops: &[LoopLabels], name: Option<String>) {
+        self.match_keyword(Keyword::Do);
+        let l1 = self.new_label();
+        let l2 = self.new_label();
+        let l3 = self.new_label();
+        let from = self.binary(0);
+        self.coerce(&from, &Type::Word, "do count");
+        self.emit_line(String::from("SUBQ.W #1,D0"));
+        self.emit_line(format!("BMI {}", l3));
+        self.post_label(&l1);
+        self.push(&Type::Word);
+        let labels = LoopLabels::new(name, &l3, &l2, 2);
+        self.loop_block(loops, labels);
+        self.post_label(&l2);
+        self.emit_line(String::from("MOVE.W (SP)+,D0"));
+        self.emit_line(format!("DBRA D0,{}", l1));
+        self.post_label(&l3);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{ReaderArg, TestReader};
+    use crate::writer::TestWriter;
+    use std::fs;
+
+    fn output(index: usize) -> String {
+        let outputs = fs::read_to_string("./test_data/assembly_outputs.txt")
+            .unwrap()
+            .replace("\r", "");
+        let outputs = outputs
+            .split("\n[[[]]]")
+            .map(String::from)
+            .collect::<Vec<String>>();
+        String::from(&outputs[index])
+    }
+
+    #[test]
+    fn given_single_term_expression_output_move_instruction() {
+        let mut reader = TestReader::new();
+        reader.read(ReaderArg::Raw(String::from("1"))).unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(0), writer.output);
+    }
+
+    #[test]
+    fn given_add_operation_output_add_instructions() {
+        let mut reader = TestReader::new();
+        reader.read(ReaderArg::Raw(String::from("1 + 2"))).unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(1), writer.output);
+    }
+
+    #[test]
+    fn given_subtract_operation_output_subtract_instructions() {
+        let mut reader = TestReader::new();
+        reader.read(ReaderArg::Raw(String::from("1-2"))).unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(2), writer.output);
+    }
+
+    #[test]
+    fn given_multiple_operators_output_correct_assembly() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("1-2+3-4+7")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(3), writer.output);
+    }
+
+    #[test]
+    fn given_multiply_output_multiply_assembly() {
+        let mut reader = TestReader::new();
+        reader.read(ReaderArg::Raw(String::from("2*3"))).unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(4), writer.output);
+    }
+
+    #[test]
+    fn given_divide_output_divide_assembly() {
+        let mut reader = TestReader::new();
+        reader.read(ReaderArg::Raw(String::from("2/3"))).unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(5), writer.output);
+    }
+
+    #[test]
+    fn given_parentheses_output_correct_assembly() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("( 1+2 )")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(6), writer.output);
+    }
+
+    #[test]
+    fn given_complex_arithmetic_output_correct_assembly() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("( 1 + 2)/((3 + 4)+(5 - 6))")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(7), writer.output);
+    }
+
+    #[test]
+    fn given_multi_char_identifier() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var jake;\njake = 10")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.statement(&[]);
+        compiler.assignment();
+
+        assert_eq!(output(8), writer.output);
+    }
+
+    #[test]
+    fn given_arbitray_whitespace_should_output_correctly() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "  var b;\n  b =   1 +   3    / 2",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.statement(&[]);
+        compiler.assignment();
+
+        assert_eq!(output(9), writer.output);
+    }
+    #[test]
+    fn given_multi_digit_literal_output_full_value() {
+        let mut reader = TestReader::new();
+        reader.read(ReaderArg::Raw(String::from("12 + 3"))).unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(10), writer.output);
+    }
+
+    #[test]
+    fn given_radix_literals_output_decimal_immediates() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("$1F + %1010 + 0o17 + 1_000")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+
+        assert_eq!(output(11), writer.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Integer literal 4294967296 does not fit in a 32-bit operand")]
+    fn given_literal_wider_than_operand_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("0x1_0000_0000")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.expression();
+    }
+    #[test]
+    #[should_panic(expected = "Name expected")]
+    fn given_keyword_as_assignment_target_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("else = 1")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.assignment();
+    }
+    #[test]
+    fn given_multi_line_block_output_each_statement() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b;\r\n{\r\n    a = 1;\r\n\x0c    b = 2;\n}\n",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.statement(&[]);
+        compiler.block(&[]);
+
+        assert_eq!(output(12), writer.output);
+    }
+    #[test]
+    fn given_comments_skip_them_by_default() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b;\n{\n    // set a\n    a = 1; /* set b */\n    b = 2;\n}",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.statement(&[]);
+        compiler.block(&[]);
+
+        assert_eq!(output(12), writer.output);
+    }
+
+    #[test]
+    fn given_comments_option_pass_comments_into_output() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b;\n{\n    // set a\n    a = 1; /* set b */\n    b = 2;\n}",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.set_options(Options {
+            comments: true,
+            ..Options::default()
+        });
+        compiler.init();
+
+        compiler.statement(&[]);
+        compiler.block(&[]);
+
+        assert_eq!(output(13), writer.output);
+    }
+    #[test]
+    fn given_if_else_with_condition_output_compare_and_branch() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b;\nif a < 5 { b = 1; } else { b = 2; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(14), writer.output);
+    }
+
+    #[test]
+    fn given_boolean_operators_output_logic_instructions() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b; not a = b and true xor false",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.statement(&[]);
+        compiler.bool_expression();
+
+        assert_eq!(output(15), writer.output);
+    }
+    #[test]
+    fn given_while_and_loop_output_branch_structure() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b, c; { while a { b = 1; } loop { c = 2; } }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.statement(&[]);
+        compiler.block(&[]);
+
+        assert_eq!(output(16), writer.output);
+    }
+    #[test]
+    #[should_panic(expected = "break outside of a loop")]
+    fn given_break_outside_loop_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a; { if a { break; } }")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.statement(&[]);
+        compiler.block(&[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "No enclosing loop named inner")]
+    fn given_break_to_unknown_loop_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "{ outer: loop { break inner; } }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.block(&[]);
+    }
+    #[test]
+    fn given_else_if_chain_output_nested_branches() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b, c;\nif a { b = 1; } else if c { b = 2; } else { b = 3; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(17), writer.output);
+    }
+
+    #[test]
+    fn given_statement_sequence_output_each_statement() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a, b = -4;\nproc tick() { a = a + 1; }\na = 1;\nif a { tick(); }\nb = 2;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(18), writer.output);
+    }
+    #[test]
+    #[should_panic(expected = "Undeclared name b")]
+    fn given_undeclared_variable_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a; a = b;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate variable name a, first declared at line 1")]
+    fn given_duplicate_declaration_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a, b; var a;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+    #[test]
+    #[should_panic(expected = "a is a variable, not a procedure")]
+    fn given_call_to_variable_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a; a();")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "Undeclared name b")]
+    fn given_variable_used_outside_its_block_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var a; if a { var b; } a = b;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    fn given_procedure_with_parameters_output_frame_and_call() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "var x, y;\nproc swap(var a, var b) { var t; t = a; a = b; b = t; }\nproc set(n, var r) { r = n; }\nswap(x, y);\nset(x + 1, y);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(19), writer.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "p takes 2 argument(s)")]
+    fn given_wrong_argument_count_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("proc p(a, b) { } p(1);")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "Name expected")]
+    fn given_expression_for_reference_parameter_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("proc p(var a) { } p(1);")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "Procedures must be declared at the top level")]
+    fn given_procedure_inside_block_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a; if a { proc p() { } }")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    fn given_function_output_return_through_epilogue() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "func sq(n) { return n * n; }\nvar a;\na = sq(3) + 1;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(20), writer.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "return outside of a procedure")]
+    fn given_return_outside_procedure_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a; return a;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "p is a procedure and has no value")]
+    fn given_procedure_call_in_expression_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a; proc p() { } a = p();")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    fn given_locals_in_nested_blocks_output_frame_slots() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "proc p(n) {\n    var a = n;\n    { var b = a + 1; a = b; }\n    { var c, d = 2; c = d; }\n}",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(21), writer.output);
+    }
+
+    #[test]
+    fn given_mixed_sizes_output_sized_instructions_and_widening() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "byte b = -1;\nword w;\nlong l = 100000;\nw = b + w;\nl = l * w;\nb = byte(l);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(22), writer.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Type mismatch: cannot assign word to byte b")]
+    fn given_narrowing_assignment_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("byte b; word w; b = w;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "Type mismatch: cannot assign word to byte b")]
+    fn given_constant_too_big_for_assignment_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("byte b; b = 300;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "Type mismatch: cannot assign long to word argument 1 of p")]
+    fn given_long_argument_for_word_parameter_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "long l; proc p(word a) { } p(l);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "Initial value 300 does not fit in a byte")]
+    fn given_initial_value_too_wide_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("byte b = 300;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    fn given_string_literals_output_deduplicated_pool() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "byte *msg;\nbyte c = 'A';\nproc put(var byte s) { c = s; }\nmsg = \"Hi, it's\\n\";\nput(\"Hi, it's\\n\");\nput(\"bye\");\nc = '\\t';",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(23), writer.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Type mismatch: cannot pass a string as var word argument 1 of p")]
+    fn given_string_for_word_reference_parameter_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "proc p(var word w) { } p(\"no\");",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    fn given_arrays_output_scaled_index_addressing() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word a[10];\nlong t[3];\nproc fill(var word v[10], n) {\n    byte s[3];\n    s[n] = 1;\n    v[n] = s[2];\n}\nfill(a, 9);\nt[a[2]] = a[3];",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(24), writer.output);
+    }
+
+    #[test]
+    fn given_bounds_check_option_output_chk_before_indexing() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a[4], i; a[i] = 1;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+        compiler.set_options(Options {
+            bounds_check: true,
+            ..Options::default()
+        });
+
+        compiler.program();
+
+        assert_eq!(output(25), writer.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "a is an array and needs an index")]
+    fn given_array_without_index_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a[4], b; b = a;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "a is not an array")]
+    fn given_index_on_variable_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a, b; b = a[0];")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "Parameter v is a word[4] and must be passed by var")]
+    fn given_array_value_parameter_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("proc p(word v[4]) { }")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    fn given_records_output_field_displacements() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "type Point { word x, y; }\ntype Shape { byte kind; Point corner; Point path[3]; }\nShape s;\nproc move(var Point p, dx) { p.x = p.x + dx; }\n{ Point q; q.y = s.corner.x; s.path[q.y].y = q.y; }\nmove(s.corner, 2);",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+
+        assert_eq!(output(26), writer.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Point has no field z")]
+    fn given_unknown_field_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "type Point { word x, y; } Point p; p.z = 1;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "p is a record and needs a field")]
+    fn given_record_without_field_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "type Point { word x, y; } Point p; var a; a = p;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    #[should_panic(expected = "Point is a type, not a variable")]
+    fn given_type_used_as_variable_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "type Point { word x, y; } var a; a = Point;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
+    }
+
+    #[test]
+    fn given_pointers_output_address_register_code() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "type Node { word value; Node *next; }\nNode nodes[2];\nNode *head;\nword *w;\nhead = &nodes[0];\nhead^.next = &nodes[1];\nw = &head^.value + 1;\n*w = 5;\nwhile head <> nil { head = head^.next; }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
 
-    fn expected(&mut self, expected: String) {
-        self.abort(format!("{} expected", expected));
+        assert_eq!(output(27), writer.output);
     }
 
-    fn emit(&mut self, s: String) {
-        self.writer.write(format!("{}", s));
-    }
+    #[test]
+    #[should_panic(expected = "Type mismatch: word is not a pointer")]
+    fn given_dereference_of_number_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var a, b; b = *a;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn emit_line(&mut self, s: String) {
-        self.writer.writeln(format!("{}", s));
+        compiler.program();
     }
 
-    fn match_char(&mut self, x: &char) -> io::Result<()> {
-        if self.lookahead.unwrap() == *x {
-            self.get_char()?;
-            self.whitespace();
-        } else {
-            self.expected(format!("\"{}\"", x));
-        }
-
-        Ok(())
-    }
+    #[test]
+    #[should_panic(expected = "Type mismatch: cannot assign long* to word* p")]
+    fn given_pointers_to_different_types_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("word *p; long *q; p = q;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn is_alpha(&self, x: &char) -> bool {
-        x.is_alphabetic()
+        compiler.program();
     }
 
-    fn is_digit(&self, x: &char) -> bool {
-        x.is_digit(10)
-    }
+    #[test]
+    #[should_panic(expected = "Type mismatch: word* is not a number")]
+    fn given_pointer_in_arithmetic_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("word *p; var a; a = p * 2;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn is_alphanum(&self, x: &char) -> bool {
-        self.is_alpha(x) || self.is_digit(x)
+        compiler.program();
     }
 
-    fn get_name(&mut self) -> String {
-        if !self.is_alpha(&self.lookahead.unwrap()) {
-            self.expected(String::from("Name"));
-        }
-
-        let mut token = String::new();
-        while self.is_alphanum(&self.lookahead.unwrap()) {
-            token.push(self.lookahead.unwrap());
-            self.get_char().unwrap();
-        }
+    #[test]
+    fn given_constants_output_immediates_without_storage() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "const N = 4, LAST = N, BIG = 100000, NEG = -N;\nword a[N];\nword w = NEG;\nlong l;\na[LAST - 1] = N * w;\nl = BIG + w;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-        self.whitespace();
+        compiler.program();
 
-        // let name = self.lookahead.unwrap();
-        // self.get_char().unwrap();
-        token
+        assert_eq!(output(28), writer.output);
     }
 
-    fn get_num(&mut self) -> char {
-        if !self.is_digit(&self.lookahead.unwrap()) {
-            self.expected(String::from("Integer"));
-        }
+    #[test]
+    #[should_panic(expected = "Cannot assign to constant N")]
+    fn given_assignment_to_constant_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("const N = 4; N = 5;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-        let num = self.lookahead;
-        self.get_char().unwrap();
-        self.whitespace();
-        num.unwrap()
+        compiler.program();
     }
 
-    fn multiply(&mut self) {
-        self.match_char(&'*').unwrap();
-        self.factor();
-        self.emit_line(String::from("MULS (SP)+,D0"));
-    }
+    #[test]
+    #[should_panic(expected = "n is not a constant")]
+    fn given_variable_as_array_length_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("var n = 4; var a[n];")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn divide(&mut self) {
-        self.match_char(&'/').unwrap();
-        self.factor();
-        self.emit_line(String::from("MOVE (SP)+,D1"));
-        self.emit_line(String::from("DIVS D1,D0"));
+        compiler.program();
     }
 
-    fn term(&mut self) {
-        self.factor();
-        if self.lookahead != None && ['*', '/'].contains(&self.lookahead.unwrap()) {
-            self.emit_line(String::from("MOVE D0,-(SP)"));
-            match self.lookahead {
-                Some(c) => match c {
-                    '*' => self.multiply(),
-                    '/' => self.divide(),
-                    _ => self.expected(String::from("Operator")),
-                },
-                None => (),
-            }
-        }
-    }
+    #[test]
+    fn given_enum_and_sparse_case_output_compare_chain() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "enum Color { Red, Green = 5, Blue }\nColor c = Blue;\nword x;\ncase c {\n    Red: { x = 1; }\n    Green, 10..20: { x = 2; }\n    else { x = 3; }\n}",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn ident(&mut self) {
-        let name = self.get_name();
-        if self.lookahead.unwrap() == '(' {
-            self.match_char(&'(');
-            self.match_char(&')');
-            self.emit_line(format!("BSR {}", name));
-        } else {
-            self.emit_line(format!("MOVE {}(PC),D0", name));
-        }
-    }
+        compiler.program();
 
-    fn factor(&mut self) {
-        if self.lookahead.unwrap() == '(' {
-            self.match_char(&'(');
-            self.expression();
-            self.match_char(&')');
-        } else if self.is_alpha(&self.lookahead.unwrap()) {
-            self.ident();
-        } else {
-            let factor = self.get_num();
-            self.emit_line(format!("MOVE #{},D0", factor));
-        }
+        assert_eq!(output(29), writer.output);
     }
 
-    fn add(&mut self) {
-        self.match_char(&'+').unwrap();
-        self.term();
-        self.emit_line(String::from("ADD (SP)+,D0"));
-    }
+    #[test]
+    fn given_dense_case_output_jump_table() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "byte b;\nword x;\ncase b {\n    1: { x = 10; }\n    2, 4: { x = 20; }\n    5..6: { x = 30; }\n}",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn subtract(&mut self) {
-        self.match_char(&'-').unwrap();
-        self.term();
-        self.emit_line(String::from("SUB (SP)+,D0"));
-        self.emit_line(String::from("NEG D0"));
-    }
+        compiler.program();
 
-    fn is_addop(&self, c: &char) -> bool {
-        ['+', '-'].contains(c)
+        assert_eq!(output(30), writer.output);
     }
 
-    fn is_whitepace(&mut self) -> bool {
-        match self.lookahead {
-            Some(c) => [' ', '\t'].contains(&c),
-            None => false,
-        }
-    }
+    #[test]
+    #[should_panic(expected = "Duplicate case label 3")]
+    fn given_duplicate_case_label_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word x; case x { 1..5: { } 3: { } }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn whitespace(&mut self) {
-        while self.is_whitepace() {
-            self.get_char();
-        }
+        compiler.program();
     }
 
-    pub fn expression(&mut self) {
-        if self.is_addop(&self.lookahead.unwrap()) {
-            self.emit_line(String::from("CLR D0"));
-        } else {
-            self.term();
-        }
+    #[test]
+    #[should_panic(expected = "Case label 40000 does not fit in a word")]
+    fn given_case_label_too_big_for_selector_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word x; case x { 40000: { } }",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-        while self.lookahead != None && self.is_addop(&self.lookahead.unwrap()) {
-            self.emit_line(String::from("MOVE D0,-(SP)"));
-            match self.lookahead {
-                Some(c) => match c {
-                    '+' => self.add(),
-                    '-' => self.subtract(),
-                    _ => {
-                        self.term();
-                    }
-                },
-                None => (),
-            }
-        }
+        compiler.program();
     }
 
-    pub fn assignment(&mut self) {
-        self.whitespace();
-        let name = self.get_name();
-        self.match_char(&'=');
-        self.expression();
-        self.emit_line(format!("LEA {}(PC),A0", name));
-        self.emit_line(format!("MOVE D0,(A0)"));
-    }
+    #[test]
+    #[should_panic(expected = "Case range 5..1 is empty")]
+    fn given_empty_case_range_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("word x; case x { 5..1: { } }")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    pub fn statement(&mut self) {
-        self.assignment();
-        self.match_char(&';');
+        compiler.program();
     }
 
-    pub fn block(&mut self) {
-        self.match_char(&'{');
-        while self.lookahead.unwrap() != '}' {
-            self.statement();
-        }
-        self.match_char(&'}');
-    }
+    #[test]
+    #[should_panic(expected = "Duplicate name A, first declared at line 1")]
+    fn given_enum_member_declared_twice_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("enum E { A, B, A }")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    pub fn program(&mut self) {
-        self.whitespace();
-        self.do_if();
+        compiler.program();
     }
 
-    fn new_label(&mut self) -> String {
-        let label = format!("L{:0>2}", self.l_count);
-        self.l_count += 1;
-        return label;
-    }
+    #[test]
+    fn given_unary_operators_output_neg_and_not() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word a, b;\na = 2 * -3;\nb = -(a + 1) * +2;\na = ~b - -a;\nb = a * not a;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn post_label(&mut self) {
-        let label = self.new_label();
-        self.emit(format!("{}: ", label));
-    }
+        compiler.program();
 
-    fn keyword(&mut self, keyword: &str) {
-        for c in keyword.chars() {}
+        assert_eq!(output(31), writer.output);
     }
 
-    fn condition(&mut self) {
-        self.emit_line(String::from("CONDITION"));
-    }
+    #[test]
+    #[should_panic(expected = "Type mismatch: word* is not a number")]
+    fn given_negated_pointer_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("word *p; long l; l = -p;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn do_if(&mut self) {
-        if !(self.get_name() == String::from("if")) {
-            self.expected(String::from(
-                "Expected if-else block to start with if keyword",
-            ));
-        }
+        compiler.program();
+    }
 
-        self.condition();
-        let l1 = self.new_label();
-        let mut l2 = l1.clone();
-        self.emit_line(format!("BEQ {}", l1));
-        self.block();
+    #[test]
+    fn given_binary_operators_output_by_precedence() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word a, b;\nlong l;\na = 2 * 3 * 4;\nb = a mod 5 + a << 2 >> 1;\nl = l mod a;\nb = a & 12 | b ^ 3;\na = a < b = 1 < 2;",
+            )))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-        if (self.get_name() == String::from("else")) {
-            l2 = self.new_label();
-            self.emit_line(format!("BRA {}", l2));
-            self.post_label();
-            self.block();
-        }
+        compiler.program();
 
-        self.emit_line(format!("{}: ", l2));
+        assert_eq!(output(32), writer.output);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::reader::{ReaderArg, TestReader};
-    use crate::writer::TestWriter;
-    use std::fs;
+    #[test]
+    #[should_panic(expected = "Type mismatch: word* is not a number")]
+    fn given_shifted_pointer_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("word *p; long l; l = p << 1;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
 
-    fn output(index: usize) -> String {
-        let mut outputs = fs::read_to_string("./test_data/assembly_outputs.txt")
-            .unwrap()
-            .replace("\r", "");
-        let outputs = outputs
-            .split("\n[[[]]]")
-            .map(|s| String::from(s))
-            .collect::<Vec<String>>();
-        String::from(&outputs[index])
+        compiler.program();
     }
 
     #[test]
-    fn given_single_term_expression_output_move_instruction() {
+    fn given_and_or_in_conditions_output_branch_chains() {
         let mut reader = TestReader::new();
-        reader.read(ReaderArg::Raw(String::from("1"))).unwrap();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "word *p;\nword a, b;\nif p <> nil and p^ > 3 { a = 1; }\nwhile a < 10 or not b { a = a + 1; }\nb = a and b;",
+            )))
+            .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.expression();
+        compiler.program();
 
-        assert_eq!(output(0), writer.output);
+        assert_eq!(output(33), writer.output);
     }
 
     #[test]
-    fn given_add_operation_output_add_instructions() {
+    fn given_constant_subexpressions_output_folded_loads() {
         let mut reader = TestReader::new();
-        reader.read(ReaderArg::Raw(String::from("1 + 2"))).unwrap();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "const N = 10;\nword a;\nlong l;\na = (N + 2) * 3 - a;\nl = (long(N) << 12) * 40;\na = byte(300) + -N mod 3;\nwhile 1 < 2 and N { a = a - 1; }\nif not true or 0 { a = 0; }",
+            )))
+            .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.expression();
+        compiler.program();
 
-        assert_eq!(output(1), writer.output);
+        assert_eq!(output(34), writer.output);
     }
 
     #[test]
-    fn given_subtract_operation_output_subtract_instructions() {
+    #[should_panic(expected = "Division by zero in 1 / 0")]
+    fn given_constant_division_by_zero_should_abort() {
         let mut reader = TestReader::new();
-        reader.read(ReaderArg::Raw(String::from("1-2"))).unwrap();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "const Z = 0; word a; a = 1 / Z;",
+            )))
+            .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.expression();
-
-        assert_eq!(output(2), writer.output);
+        compiler.program();
     }
 
     #[test]
-    fn given_multiple_operators_output_correct_assembly() {
+    #[should_panic(expected = "30000 * 3 overflows a word")]
+    fn given_constant_overflowing_word_should_abort() {
         let mut reader = TestReader::new();
         reader
-            .read(ReaderArg::Raw(String::from("1-2+3-4+7")))
+            .read(ReaderArg::Raw(String::from("word a; a = 30000 * 3;")))
             .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.expression();
+        compiler.program();
+    }
 
-        assert_eq!(output(3), writer.output);
+    #[test]
+    #[should_panic(expected = "200 * 200 overflows a word")]
+    fn given_word_constants_overflowing_into_long_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("long f; f = 200 * 200;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
     }
 
     #[test]
-    fn given_multiply_output_multiply_assembly() {
+    #[should_panic(expected = "32767 + 1 overflows a word")]
+    fn given_constant_past_largest_word_should_abort() {
         let mut reader = TestReader::new();
-        reader.read(ReaderArg::Raw(String::from("2*3"))).unwrap();
+        reader
+            .read(ReaderArg::Raw(String::from("var a; a = 32767 + 1;")))
+            .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.expression();
+        compiler.program();
+    }
 
-        assert_eq!(output(4), writer.output);
+    #[test]
+    #[should_panic(expected = "2147483647 * 2 overflows a long")]
+    fn given_constant_overflowing_long_should_abort() {
+        let mut reader = TestReader::new();
+        reader
+            .read(ReaderArg::Raw(String::from("long x; x = $7FFFFFFF * 2;")))
+            .unwrap();
+        let mut writer = TestWriter::new();
+        let mut compiler = Compiler::new(reader, &mut writer);
+        compiler.init();
+
+        compiler.program();
     }
 
     #[test]
-    fn given_divide_output_divide_assembly() {
+    fn given_unsigned_long_constants_output_folded_signed_load() {
         let mut reader = TestReader::new();
-        reader.read(ReaderArg::Raw(String::from("2/3"))).unwrap();
+        reader
+            .read(ReaderArg::Raw(String::from(
+                "long x; x = $FFFF0000 | $FFFF;",
+            )))
+            .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.expression();
+        compiler.program();
 
-        assert_eq!(output(5), writer.output);
+        assert_eq!(output(36), writer.output);
     }
 
     #[test]
-    fn given_parentheses_output_correct_assembly() {
+    #[should_panic(expected = "-(4294967295) overflows a long")]
+    fn given_negated_constant_beyond_long_should_abort() {
         let mut reader = TestReader::new();
         reader
-            .read(ReaderArg::Raw(String::from("( 1+2 )")))
+            .read(ReaderArg::Raw(String::from("long l; l = -$FFFFFFFF;")))
             .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.expression();
-
-        assert_eq!(output(6), writer.output);
+        compiler.program();
     }
 
     #[test]
-    fn given_complex_arithmetic_output_correct_assembly() {
+    fn given_negated_constants_output_smallest_type_holding_them() {
         let mut reader = TestReader::new();
         reader
-            .read(ReaderArg::Raw(String::from("( 1 + 2)/((3 + 4)+(5 - 6))")))
+            .read(ReaderArg::Raw(String::from(
+                "byte b; word w; long l;\nb = -128;\nw = -32768;\nl = ~$FFFFFFFF;\nb = ~byte(5);",
+            )))
             .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.expression();
+        compiler.program();
 
-        assert_eq!(output(7), writer.output);
+        assert_eq!(output(37), writer.output);
     }
 
     #[test]
-    fn given_multi_char_identifier() {
+    fn given_conditional_expressions_output_branches_to_values() {
         let mut reader = TestReader::new();
         reader
-            .read(ReaderArg::Raw(String::from("jake = 10")))
+            .read(ReaderArg::Raw(String::from(
+                "byte b;\nword a;\nlong l;\na = 2 * (if a > b then a else b);\nl = a < 0 ? l : b;",
+            )))
             .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.assignment();
+        compiler.program();
 
-        assert_eq!(output(8), writer.output);
+        assert_eq!(output(35), writer.output);
     }
 
     #[test]
-    fn given_arbitray_whitespace_should_output_correctly() {
+    #[should_panic(expected = "Type mismatch: cannot choose between word* and byte")]
+    fn given_conditional_choosing_pointer_or_number_should_abort() {
         let mut reader = TestReader::new();
         reader
-            .read(ReaderArg::Raw(String::from("  b =   1 +   3    / 2")))
+            .read(ReaderArg::Raw(String::from(
+                "word *p; word a; a = a ? p : 1;",
+            )))
             .unwrap();
         let mut writer = TestWriter::new();
         let mut compiler = Compiler::new(reader, &mut writer);
         compiler.init();
 
-        compiler.assignment();
-
-        assert_eq!(output(9), writer.output);
+        compiler.program();
     }
 }
@@ -1,20 +1,42 @@
+#[cfg(test)]
 mod assembly_interpreter;
 mod compiler;
 mod reader;
+mod scanner;
+mod symbol_table;
 mod writer;
-use compiler::Compiler;
+use compiler::{Compiler, Options};
 use reader::{FileReader, Reader, ReaderArg, StdinReader};
+use std::env;
 use std::io;
-use writer::{StdoutWriter, Writer};
+use writer::StdoutWriter;
 
 fn main() -> io::Result<()> {
-    let mut reader = FileReader::new();
-    reader
-        .read(ReaderArg::FilePath("./test_data/input.xx".to_string()))
-        .unwrap();
+    let mut options = Options::default();
+    let mut path = String::from("./test_data/input.xx");
+    for arg in env::args().skip(1) {
+        match &arg[..] {
+            "--comments" => options.comments = true,
+            "--bounds-check" => options.bounds_check = true,
+            _ => path = arg,
+        }
+    }
+
     let mut writer = StdoutWriter {};
-    let mut cradle = Compiler::new(reader, &mut writer);
-    cradle.init();
-    cradle.program();
+    if path == "-" {
+        let mut reader = StdinReader::new();
+        reader.read(ReaderArg::None)?;
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.set_options(options);
+        cradle.init();
+        cradle.program();
+    } else {
+        let mut reader = FileReader::new();
+        reader.read(ReaderArg::FilePath(path))?;
+        let mut cradle = Compiler::new(reader, &mut writer);
+        cradle.set_options(options);
+        cradle.init();
+        cradle.program();
+    }
     Ok(())
 }
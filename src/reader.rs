@@ -1,8 +1,10 @@
 use std::fs;
 use std::io;
+use std::io::Read;
 
 #[derive(Debug)]
 pub enum ReaderArg {
+    #[cfg(test)]
     Raw(String),
     FilePath(String),
     None,
@@ -12,6 +14,7 @@ pub struct StdinReader {
     buffer: Vec<char>,
 }
 
+#[cfg(test)]
 pub struct TestReader {
     buffer: Vec<char>,
 }
@@ -49,6 +52,7 @@ impl StdinReader {
     }
 }
 
+#[cfg(test)]
 impl TestReader {
     pub fn new() -> Self {
         Self { buffer: Vec::new() }
@@ -61,13 +65,10 @@ pub trait Reader {
 }
 
 impl Reader for StdinReader {
-    fn read(&mut self, arg: ReaderArg) -> io::Result<()> {
+    fn read(&mut self, _arg: ReaderArg) -> io::Result<()> {
         let mut temp_buf: String = String::new();
-        std::io::stdin().read_line(&mut temp_buf)?;
-        self.buffer = temp_buf
-            .chars()
-            .filter(|c| ![&'\n', &'\r'].contains(&c))
-            .collect::<Vec<char>>();
+        std::io::stdin().read_to_string(&mut temp_buf)?;
+        self.buffer = temp_buf.chars().collect::<Vec<char>>();
 
         Ok(())
     }
@@ -77,14 +78,12 @@ impl Reader for StdinReader {
     }
 }
 
+#[cfg(test)]
 impl Reader for TestReader {
     fn read(&mut self, arg: ReaderArg) -> io::Result<()> {
         match arg {
             ReaderArg::Raw(s) => {
-                self.buffer = s
-                    .chars()
-                    .filter(|c| ![&'\n', &'\r'].contains(&c))
-                    .collect::<Vec<char>>();
+                self.buffer = s.chars().collect::<Vec<char>>();
             }
             _ => panic!("Invalid argument!"),
         }
@@ -0,0 +1,847 @@
+use std::fmt;
+
+/// Location of a token in the source: a byte range plus the line and column
+/// (both 1-based) of its first character.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keyword {
+    If,
+    Else,
+    Not,
+    And,
+    Or,
+    Xor,
+    True,
+    False,
+    While,
+    Loop,
+    Repeat,
+    Until,
+    For,
+    To,
+    Do,
+    Break,
+    Continue,
+    Var,
+    Proc,
+    Func,
+    Return,
+    Byte,
+    Word,
+    Long,
+    Type,
+    Nil,
+    Const,
+    Enum,
+    Case,
+    Then,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Ampersand,
+    Caret,
+    Tilde,
+    Bar,
+    ShiftLeft,
+    ShiftRight,
+    Mod,
+    Equals,
+    NotEquals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Punct {
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Semicolon,
+    Colon,
+    Comma,
+    Dot,
+    /// `..`, between the bounds of a range.
+    Range,
+    Question,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Keyword(Keyword),
+    Number(i64),
+    /// A character literal, as its byte value.
+    Char(u8),
+    /// A string literal with its escapes already resolved.
+    Str(Vec<u8>),
+    Operator(Operator),
+    Punct(Punct),
+    Eof,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Keyword {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "if" => Some(Keyword::If),
+            "else" => Some(Keyword::Else),
+            "not" => Some(Keyword::Not),
+            "and" => Some(Keyword::And),
+            "or" => Some(Keyword::Or),
+            "xor" => Some(Keyword::Xor),
+            "true" => Some(Keyword::True),
+            "false" => Some(Keyword::False),
+            "while" => Some(Keyword::While),
+            "loop" => Some(Keyword::Loop),
+            "repeat" => Some(Keyword::Repeat),
+            "until" => Some(Keyword::Until),
+            "for" => Some(Keyword::For),
+            "to" => Some(Keyword::To),
+            "do" => Some(Keyword::Do),
+            "break" => Some(Keyword::Break),
+            "continue" => Some(Keyword::Continue),
+            "var" => Some(Keyword::Var),
+            "proc" => Some(Keyword::Proc),
+            "func" => Some(Keyword::Func),
+            "return" => Some(Keyword::Return),
+            "byte" => Some(Keyword::Byte),
+            "word" => Some(Keyword::Word),
+            "long" => Some(Keyword::Long),
+            "type" => Some(Keyword::Type),
+            "nil" => Some(Keyword::Nil),
+            "const" => Some(Keyword::Const),
+            "enum" => Some(Keyword::Enum),
+            "case" => Some(Keyword::Case),
+            "then" => Some(Keyword::Then),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Keyword::If => "if",
+            Keyword::Else => "else",
+            Keyword::Not => "not",
+            Keyword::And => "and",
+            Keyword::Or => "or",
+            Keyword::Xor => "xor",
+            Keyword::True => "true",
+            Keyword::False => "false",
+            Keyword::While => "while",
+            Keyword::Loop => "loop",
+            Keyword::Repeat => "repeat",
+            Keyword::Until => "until",
+            Keyword::For => "for",
+            Keyword::To => "to",
+            Keyword::Do => "do",
+            Keyword::Break => "break",
+            Keyword::Continue => "continue",
+            Keyword::Var => "var",
+            Keyword::Proc => "proc",
+            Keyword::Func => "func",
+            Keyword::Return => "return",
+            Keyword::Byte => "byte",
+            Keyword::Word => "word",
+            Keyword::Long => "long",
+            Keyword::Type => "type",
+            Keyword::Nil => "nil",
+            Keyword::Const => "const",
+            Keyword::Enum => "enum",
+            Keyword::Case => "case",
+            Keyword::Then => "then",
+        }
+    }
+}
+
+impl Operator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Star => "*",
+            Operator::Slash => "/",
+            Operator::Ampersand => "&",
+            Operator::Caret => "^",
+            Operator::Tilde => "~",
+            Operator::Bar => "|",
+            Operator::ShiftLeft => "<<",
+            Operator::ShiftRight => ">>",
+            Operator::Mod => "mod",
+            Operator::Equals => "=",
+            Operator::NotEquals => "<>",
+            Operator::Less => "<",
+            Operator::LessEquals => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterEquals => ">=",
+        }
+    }
+}
+
+impl Punct {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Punct::LParen => "(",
+            Punct::RParen => ")",
+            Punct::LBrace => "{",
+            Punct::RBrace => "}",
+            Punct::LBracket => "[",
+            Punct::RBracket => "]",
+            Punct::Semicolon => ";",
+            Punct::Colon => ":",
+            Punct::Comma => ",",
+            Punct::Dot => ".",
+            Punct::Range => "..",
+            Punct::Question => "?",
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Keyword(keyword) => write!(f, "{}", keyword.as_str()),
+            Token::Number(num) => write!(f, "{}", num),
+            Token::Char(c) => write!(f, "{:?}", char::from(*c)),
+            Token::Str(bytes) => write!(f, "{:?}", String::from_utf8_lossy(bytes)),
+            Token::Operator(op) => write!(f, "{}", op.as_str()),
+            Token::Punct(punct) => write!(f, "{}", punct.as_str()),
+            Token::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Scanner {
+    source: Vec<char>,
+    counter: usize,
+    offset: usize,
+    line: usize,
+    column: usize,
+    comments: Vec<String>,
+}
+
+impl Scanner {
+    pub fn new(source: Vec<char>) -> Self {
+        Self {
+            source,
+            counter: 0,
+            offset: 0,
+            line: 1,
+            column: 1,
+            comments: Vec::new(),
+        }
+    }
+
+    /// Returns the text of every comment skipped since the last call, one
+    /// entry per source line.
+    pub fn take_comments(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.comments)
+    }
+
+    fn lookahead(&self) -> Option<char> {
+        self.source.get(self.counter).copied()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source.get(self.counter + 1).copied()
+    }
+
+    /// Consumes one character, keeping the line and column up to date. A
+    /// `\r\n` pair counts as a single line break, as does a lone `\r`.
+    fn get_char(&mut self) {
+        if let Some(c) = self.lookahead() {
+            let ends_line = c == '\n' || (c == '\r' && self.peek() != Some('\n'));
+            self.counter += 1;
+            self.offset += c.len_utf8();
+            if ends_line {
+                self.line += 1;
+                self.column = 1;
+            } else if c != '\r' {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn is_whitespace(&self) -> bool {
+        match self.lookahead() {
+            Some(c) => [' ', '\t', '\n', '\r', '\x0c'].contains(&c),
+            None => false,
+        }
+    }
+
+    fn current_span(&self) -> Span {
+        Span {
+            start: self.offset,
+            end: self.offset,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// Skips whitespace along with `//` line comments and `/* */` block
+    /// comments, which may nest.
+    fn whitespace(&mut self) -> Result<(), ScanError> {
+        loop {
+            if self.is_whitespace() {
+                self.get_char();
+            } else if self.lookahead() == Some('/') && self.peek() == Some('/') {
+                self.line_comment();
+            } else if self.lookahead() == Some('/') && self.peek() == Some('*') {
+                self.block_comment()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn line_comment(&mut self) {
+        self.get_char();
+        self.get_char();
+        let mut text = String::new();
+        while let Some(c) = self.lookahead().filter(|c| !['\n', '\r'].contains(c)) {
+            text.push(c);
+            self.get_char();
+        }
+        self.comments.push(text.trim().to_string());
+    }
+
+    fn block_comment(&mut self) -> Result<(), ScanError> {
+        let start = self.current_span();
+        self.get_char();
+        self.get_char();
+        let mut text = String::new();
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.lookahead(), self.peek()) {
+                (Some('/'), Some('*')) => depth += 1,
+                (Some('*'), Some('/')) => depth -= 1,
+                (Some(c), _) => {
+                    text.push(c);
+                    self.get_char();
+                    continue;
+                }
+                (None, _) => {
+                    return Err(ScanError {
+                        message: String::from("Unterminated comment"),
+                        span: self.span_from(start),
+                    })
+                }
+            }
+            if depth > 0 {
+                text.push_str(if self.lookahead() == Some('/') {
+                    "/*"
+                } else {
+                    "*/"
+                });
+            }
+            self.get_char();
+            self.get_char();
+        }
+
+        self.comments.extend(
+            text.lines()
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty()),
+        );
+        Ok(())
+    }
+
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            end: self.offset,
+            ..start
+        }
+    }
+
+    /// Scans the next token, skipping any whitespace before it. Once the end
+    /// of the source is reached every further call returns `Token::Eof`.
+    pub fn next_token(&mut self) -> Result<(Token, Span), ScanError> {
+        self.whitespace()?;
+        let start = self.current_span();
+
+        let c = match self.lookahead() {
+            Some(c) => c,
+            None => return Ok((Token::Eof, start)),
+        };
+
+        let token = if c.is_alphabetic() {
+            self.get_name()
+        } else if c.is_ascii_digit() || ['$', '%', '@'].contains(&c) {
+            Token::Number(self.get_num(start)?)
+        } else if c == '<' || c == '>' {
+            self.relop()
+        } else if c == '\'' {
+            self.char_literal(start)?
+        } else if c == '"' {
+            self.string_literal(start)?
+        } else if c == '.' {
+            self.get_char();
+            if self.lookahead() == Some('.') {
+                self.get_char();
+                Token::Punct(Punct::Range)
+            } else {
+                Token::Punct(Punct::Dot)
+            }
+        } else {
+            let token = match c {
+                '+' => Token::Operator(Operator::Plus),
+                '-' => Token::Operator(Operator::Minus),
+                '*' => Token::Operator(Operator::Star),
+                '/' => Token::Operator(Operator::Slash),
+                '&' => Token::Operator(Operator::Ampersand),
+                '^' => Token::Operator(Operator::Caret),
+                '~' => Token::Operator(Operator::Tilde),
+                '|' => Token::Operator(Operator::Bar),
+                '=' => Token::Operator(Operator::Equals),
+                '(' => Token::Punct(Punct::LParen),
+                ')' => Token::Punct(Punct::RParen),
+                '{' => Token::Punct(Punct::LBrace),
+                '}' => Token::Punct(Punct::RBrace),
+                '[' => Token::Punct(Punct::LBracket),
+                ']' => Token::Punct(Punct::RBracket),
+                ';' => Token::Punct(Punct::Semicolon),
+                ':' => Token::Punct(Punct::Colon),
+                ',' => Token::Punct(Punct::Comma),
+                '?' => Token::Punct(Punct::Question),
+                x => {
+                    self.get_char();
+                    return Err(ScanError {
+                        message: format!("Unexpected character {:?}", x),
+                        span: self.span_from(start),
+                    });
+                }
+            };
+            self.get_char();
+            token
+        };
+
+        Ok((token, self.span_from(start)))
+    }
+
+    fn relop(&mut self) -> Token {
+        let first = self.lookahead();
+        self.get_char();
+        let op = match (first, self.lookahead()) {
+            (Some('<'), Some('>')) => Operator::NotEquals,
+            (Some('<'), Some('<')) => Operator::ShiftLeft,
+            (Some('>'), Some('>')) => Operator::ShiftRight,
+            (Some('<'), Some('=')) => Operator::LessEquals,
+            (Some('>'), Some('=')) => Operator::GreaterEquals,
+            (Some('<'), _) => return Token::Operator(Operator::Less),
+            _ => return Token::Operator(Operator::Greater),
+        };
+        self.get_char();
+        Token::Operator(op)
+    }
+
+    fn get_name(&mut self) -> Token {
+        let mut name = String::new();
+        while let Some(c) = self.lookahead().filter(|c| c.is_alphanumeric()) {
+            name.push(c);
+            self.get_char();
+        }
+
+        match Keyword::from_name(&name) {
+            Some(keyword) => Token::Keyword(keyword),
+            None if name == "mod" => Token::Operator(Operator::Mod),
+            None => Token::Ident(name),
+        }
+    }
+
+    /// Reads the radix prefix of an integer literal, if any: `$`, `%` and `@`
+    /// in the Motorola style, or `0x`, `0b` and `0o` in the C style.
+    fn get_radix(&mut self) -> u32 {
+        let radix = match (self.lookahead(), self.peek()) {
+            (Some('$'), _) => 16,
+            (Some('%'), _) => 2,
+            (Some('@'), _) => 8,
+            (Some('0'), Some('x')) | (Some('0'), Some('X')) => 16,
+            (Some('0'), Some('b')) | (Some('0'), Some('B')) => 2,
+            (Some('0'), Some('o')) | (Some('0'), Some('O')) => 8,
+            _ => return 10,
+        };
+
+        if self.lookahead() == Some('0') {
+            self.get_char();
+        }
+        self.get_char();
+        radix
+    }
+
+    /// Reads one character of a literal, resolving an escape sequence: `\n`,
+    /// `\t`, `\r`, `\0`, `\\`, `\'`, `\"` or `\x` followed by two hex
+    /// digits. Characters must fit in a byte.
+    fn literal_char(&mut self, start: Span) -> Result<u8, ScanError> {
+        let error = |scanner: &Self, message: String| ScanError {
+            message,
+            span: scanner.span_from(start),
+        };
+
+        let c = self.lookahead().unwrap();
+        self.get_char();
+        if c != '\\' {
+            if c as u32 > 0xFF {
+                return Err(error(
+                    self,
+                    format!("Character {:?} does not fit in a byte", c),
+                ));
+            }
+            return Ok(c as u8);
+        }
+
+        let escape = match self.lookahead() {
+            Some(escape) => escape,
+            None => return Err(error(self, String::from("Unterminated escape sequence"))),
+        };
+        self.get_char();
+        match escape {
+            'n' => Ok(b'\n'),
+            't' => Ok(b'\t'),
+            'r' => Ok(b'\r'),
+            '0' => Ok(0),
+            '\\' | '\'' | '"' => Ok(escape as u8),
+            'x' => {
+                let mut digits = String::new();
+                for _ in 0..2 {
+                    match self.lookahead().filter(|c| c.is_ascii_hexdigit()) {
+                        Some(c) => digits.push(c),
+                        None => {
+                            return Err(error(self, String::from("Hex escape needs two digits")))
+                        }
+                    }
+                    self.get_char();
+                }
+                Ok(u8::from_str_radix(&digits, 16).unwrap())
+            }
+            x => Err(error(self, format!("Unknown escape sequence \\{}", x))),
+        }
+    }
+
+    /// Reads a literal's characters up to the closing `quote`. Literals end
+    /// at the end of the line.
+    fn quoted(&mut self, start: Span, quote: char, what: &str) -> Result<Vec<u8>, ScanError> {
+        self.get_char();
+        let mut bytes = Vec::new();
+        loop {
+            match self.lookahead() {
+                Some(c) if c == quote => break,
+                Some(c) if c != '\n' && c != '\r' => bytes.push(self.literal_char(start)?),
+                _ => {
+                    return Err(ScanError {
+                        message: format!("Unterminated {}", what),
+                        span: self.span_from(start),
+                    })
+                }
+            }
+        }
+        self.get_char();
+        Ok(bytes)
+    }
+
+    fn char_literal(&mut self, start: Span) -> Result<Token, ScanError> {
+        let bytes = self.quoted(start, '\'', "character literal")?;
+        match bytes[..] {
+            [c] => Ok(Token::Char(c)),
+            _ => Err(ScanError {
+                message: String::from("Character literal must hold one character"),
+                span: self.span_from(start),
+            }),
+        }
+    }
+
+    fn string_literal(&mut self, start: Span) -> Result<Token, ScanError> {
+        Ok(Token::Str(self.quoted(start, '"', "string")?))
+    }
+
+    fn get_num(&mut self, start: Span) -> Result<i64, ScanError> {
+        let radix = self.get_radix();
+        let mut digits = String::new();
+        while let Some(c) = self.lookahead() {
+            if c == '_' {
+                self.get_char();
+            } else if c.is_digit(radix) {
+                digits.push(c);
+                self.get_char();
+            } else {
+                break;
+            }
+        }
+
+        if digits.is_empty() {
+            return Err(ScanError {
+                message: String::from("Integer expected"),
+                span: self.span_from(start),
+            });
+        }
+
+        i64::from_str_radix(&digits, radix).map_err(|_| ScanError {
+            message: format!("Integer literal {} is too large", digits),
+            span: self.span_from(start),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<(Token, Span)> {
+        let mut scanner = Scanner::new(source.chars().collect());
+        let mut tokens = Vec::new();
+        loop {
+            let (token, span) = scanner.next_token().unwrap();
+            tokens.push((token.clone(), span));
+            if token == Token::Eof {
+                return tokens;
+            }
+        }
+    }
+
+    #[test]
+    fn given_assignment_produce_spanned_tokens() {
+        let tokens = tokens("jake = $1F;");
+
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    Token::Ident(String::from("jake")),
+                    Span {
+                        start: 0,
+                        end: 4,
+                        line: 1,
+                        column: 1
+                    }
+                ),
+                (
+                    Token::Operator(Operator::Equals),
+                    Span {
+                        start: 5,
+                        end: 6,
+                        line: 1,
+                        column: 6
+                    }
+                ),
+                (
+                    Token::Number(31),
+                    Span {
+                        start: 7,
+                        end: 10,
+                        line: 1,
+                        column: 8
+                    }
+                ),
+                (
+                    Token::Punct(Punct::Semicolon),
+                    Span {
+                        start: 10,
+                        end: 11,
+                        line: 1,
+                        column: 11
+                    }
+                ),
+                (
+                    Token::Eof,
+                    Span {
+                        start: 11,
+                        end: 11,
+                        line: 1,
+                        column: 12
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn given_relational_operators_produce_longest_match() {
+        let tokens = tokens("a<>b<=c<d>=e>f")
+            .into_iter()
+            .filter_map(|(token, _)| match token {
+                Token::Operator(op) => Some(op),
+                _ => None,
+            })
+            .collect::<Vec<Operator>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Operator::NotEquals,
+                Operator::LessEquals,
+                Operator::Less,
+                Operator::GreaterEquals,
+                Operator::Greater,
+            ]
+        );
+    }
+
+    #[test]
+    fn given_keywords_produce_keyword_tokens() {
+        let tokens = tokens("if else iffy")
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect::<Vec<Token>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::If),
+                Token::Keyword(Keyword::Else),
+                Token::Ident(String::from("iffy")),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn given_mixed_line_endings_track_line_and_column() {
+        let positions = tokens("a\nb\r\n  c\x0c\rd")
+            .into_iter()
+            .map(|(_, span)| (span.line, span.column))
+            .collect::<Vec<(usize, usize)>>();
+
+        assert_eq!(positions, vec![(1, 1), (2, 1), (3, 3), (4, 1), (4, 2)]);
+    }
+
+    #[test]
+    fn given_comments_skip_them_and_keep_their_text() {
+        let mut scanner = Scanner::new(
+            "a // first\n/* outer /* inner */\n second */ b"
+                .chars()
+                .collect(),
+        );
+
+        assert_eq!(
+            scanner.next_token().unwrap().0,
+            Token::Ident(String::from("a"))
+        );
+        assert_eq!(
+            scanner.next_token().unwrap().0,
+            Token::Ident(String::from("b"))
+        );
+        assert_eq!(
+            scanner.take_comments(),
+            vec!["first", "outer /* inner */", "second"]
+        );
+    }
+
+    #[test]
+    fn given_unterminated_block_comment_report_error() {
+        let mut scanner = Scanner::new("/* a /* b */".chars().collect());
+
+        let error = scanner.next_token().unwrap_err();
+
+        assert_eq!(error.message, "Unterminated comment");
+    }
+
+    #[test]
+    fn given_unknown_character_report_its_position() {
+        let mut scanner = Scanner::new("a # b".chars().collect());
+        scanner.next_token().unwrap();
+
+        let error = scanner.next_token().unwrap_err();
+
+        assert_eq!(error.span.column, 3);
+    }
+
+    #[test]
+    fn given_char_and_string_literals_resolve_escapes() {
+        let tokens = tokens(r#"'a' '\n' '\x41' "hi\t\"there\"\\" """#)
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect::<Vec<Token>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Char(b'a'),
+                Token::Char(b'\n'),
+                Token::Char(b'A'),
+                Token::Str(b"hi\t\"there\"\\".to_vec()),
+                Token::Str(Vec::new()),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn given_bad_literals_report_errors() {
+        let error = |source: &str| {
+            let mut scanner = Scanner::new(source.chars().collect());
+            scanner.next_token().unwrap_err().message
+        };
+
+        assert_eq!(error("\"abc\n\""), "Unterminated string");
+        assert_eq!(error("'ab'"), "Character literal must hold one character");
+        assert_eq!(error(r"'\q'"), r"Unknown escape sequence \q");
+        assert_eq!(
+            error("'\u{3bb}'"),
+            "Character '\u{3bb}' does not fit in a byte"
+        );
+    }
+
+    #[test]
+    fn given_range_produce_range_between_numbers() {
+        let tokens = tokens("1..5 a.b")
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect::<Vec<Token>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(1),
+                Token::Punct(Punct::Range),
+                Token::Number(5),
+                Token::Ident(String::from("a")),
+                Token::Punct(Punct::Dot),
+                Token::Ident(String::from("b")),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn given_bitwise_operators_produce_operator_tokens() {
+        let tokens = tokens("a<<b>>c|d~e mod f modulo")
+            .into_iter()
+            .map(|(token, _)| token)
+            .filter(|token| !matches!(token, Token::Ident(_)))
+            .collect::<Vec<Token>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Operator(Operator::ShiftLeft),
+                Token::Operator(Operator::ShiftRight),
+                Token::Operator(Operator::Bar),
+                Token::Operator(Operator::Tilde),
+                Token::Operator(Operator::Mod),
+                Token::Eof,
+            ]
+        );
+    }
+}
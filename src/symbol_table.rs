@@ -0,0 +1,417 @@
+use crate::scanner::Span;
+use std::fmt;
+
+/// What a name stands for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Variable,
+    Constant,
+    Procedure,
+    Parameter,
+    Type,
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            SymbolKind::Variable => "variable",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Procedure => "procedure",
+            SymbolKind::Parameter => "parameter",
+            SymbolKind::Type => "type",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+/// The type a name was declared with. The integer types are ordered by
+/// width. Procedures have no value, so they are `Void`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Type {
+    Byte,
+    Word,
+    Long,
+    Void,
+    /// A number of elements stored one after another.
+    Array(Box<Type>, usize),
+    Record(Box<Record>),
+    /// The address of a value of the given type.
+    Pointer(Box<Type>),
+    /// A record type referred to by name, as a pointer's target. This lets
+    /// a record hold pointers to its own type.
+    Named(String),
+}
+
+impl Type {
+    pub fn bytes(&self) -> usize {
+        match self {
+            Type::Byte => 1,
+            Type::Word => 2,
+            Type::Long | Type::Pointer(_) => 4,
+            Type::Void | Type::Named(_) => 0,
+            Type::Array(element, length) => element.bytes() * length,
+            Type::Record(record) => record.size,
+        }
+    }
+
+    /// The boundary a value of this type must start on. The 68000 can only
+    /// reach words and longs at even addresses, and records are kept even so
+    /// they can hold them.
+    pub fn align(&self) -> usize {
+        match self {
+            Type::Byte | Type::Void | Type::Named(_) => 1,
+            Type::Word | Type::Long | Type::Record(_) | Type::Pointer(_) => 2,
+            Type::Array(element, _) => element.align(),
+        }
+    }
+
+    /// The size suffix of instructions working on this type. An array takes
+    /// the suffix of its elements, and a record is laid out in words.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Type::Byte => ".B",
+            Type::Word | Type::Record(_) => ".W",
+            Type::Long | Type::Void | Type::Pointer(_) | Type::Named(_) => ".L",
+            Type::Array(element, _) => element.suffix(),
+        }
+    }
+
+    /// Whether this is one of the integer types.
+    pub fn is_scalar(&self) -> bool {
+        matches!(self, Type::Byte | Type::Word | Type::Long)
+    }
+
+    /// Whether a value of this type is made of several others, and so does
+    /// not fit in a register.
+    pub fn is_aggregate(&self) -> bool {
+        matches!(self, Type::Array(..) | Type::Record(_))
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, Type::Pointer(_))
+    }
+
+    /// A pointer to `ty`. Records are pointed to by name.
+    pub fn pointer_to(ty: Type) -> Type {
+        match ty {
+            Type::Record(record) => Type::Pointer(Box::new(Type::Named(record.name))),
+            ty => Type::Pointer(Box::new(ty)),
+        }
+    }
+
+    /// Whether a literal can be stored in this type, as either a signed or
+    /// an unsigned number.
+    pub fn fits(&self, value: i64) -> bool {
+        let bits = 8 * self.bytes() as u32;
+        self.is_scalar() && value >= -(1 << (bits - 1)) && value < (1 << bits)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Byte => write!(f, "byte"),
+            Type::Word => write!(f, "word"),
+            Type::Long => write!(f, "long"),
+            Type::Void => write!(f, "void"),
+            Type::Array(element, length) => write!(f, "{}[{}]", element, length),
+            Type::Record(record) => write!(f, "{}", record.name),
+            Type::Pointer(ty) => write!(f, "{}*", ty),
+            Type::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A named member of a record, at a byte offset from its start.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Field {
+    pub name: String,
+    pub ty: Type,
+    pub offset: usize,
+}
+
+/// The layout of a record type. Fields are placed in declaration order,
+/// each aligned as its type needs, and the size is rounded up to even so
+/// records in an array stay aligned.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Record {
+    pub name: String,
+    pub fields: Vec<Field>,
+    pub size: usize,
+}
+
+impl Record {
+    pub fn new(name: &str, fields: Vec<(String, Type)>) -> Self {
+        let mut offset: usize = 0;
+        let fields = fields
+            .into_iter()
+            .map(|(name, ty)| {
+                let align = ty.align();
+                let start = offset.div_ceil(align) * align;
+                offset = start + ty.bytes();
+                Field {
+                    name,
+                    ty,
+                    offset: start,
+                }
+            })
+            .collect();
+        Self {
+            name: name.to_string(),
+            fields,
+            size: offset.div_ceil(2) * 2,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+/// Where the value or code behind a name lives.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    /// A labelled word in the data section.
+    Global(String),
+    /// An offset from the frame pointer A6.
+    Frame(i32),
+    /// The address of the value, stored at an offset from A6.
+    Reference(i32),
+    /// An offset from the address just computed into A0, such as that of an
+    /// array element.
+    Indirect(i32),
+    /// A value known while compiling, folded into the instructions that
+    /// use it.
+    Immediate(i64),
+    /// A label in the code.
+    Code(String),
+    /// Nothing is stored, as for a type.
+    None,
+}
+
+/// How an argument is handed to a procedure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Passing {
+    /// A copy of the value is pushed.
+    Value,
+    /// The address of a variable is pushed, so the procedure can change it.
+    Reference,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub passing: Passing,
+    pub ty: Type,
+}
+
+impl Param {
+    /// Bytes the argument takes on the stack. The stack pointer stays even,
+    /// so a byte takes a word.
+    pub fn bytes(&self) -> usize {
+        match self.passing {
+            Passing::Value => self.ty.bytes().max(2),
+            Passing::Reference => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub ty: Type,
+    pub location: Location,
+    /// Where the name was declared.
+    pub span: Span,
+    /// How each parameter of a procedure is passed.
+    pub params: Vec<Param>,
+}
+
+impl Symbol {
+    pub fn new(name: &str, kind: SymbolKind, ty: Type, location: Location, span: Span) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            ty,
+            location,
+            span,
+            params: Vec::new(),
+        }
+    }
+}
+
+/// Names visible at the current point of the program, innermost scope last.
+/// The outermost scope holds the globals and is never left.
+#[derive(Debug)]
+pub struct SymbolTable {
+    scopes: Vec<Vec<Symbol>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![Vec::new()],
+        }
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.scopes.len() == 1
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Drops the innermost scope and returns the symbols it declared.
+    pub fn leave_scope(&mut self) -> Vec<Symbol> {
+        if self.scopes.len() == 1 {
+            panic!("Cannot leave the global scope");
+        }
+        self.scopes.pop().unwrap()
+    }
+
+    /// Adds a symbol to the innermost scope. If that scope already declares
+    /// the name, where it was first declared is returned as the error. Names
+    /// in outer scopes can be shadowed.
+    pub fn declare(&mut self, symbol: Symbol) -> Result<(), Span> {
+        let scope = self.scopes.last_mut().unwrap();
+        if let Some(existing) = scope.iter().find(|s| s.name == symbol.name) {
+            return Err(existing.span);
+        }
+        scope.push(symbol);
+        Ok(())
+    }
+
+    /// Finds the innermost declaration of `name`.
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.iter().find(|s| s.name == name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(line: usize) -> Span {
+        Span {
+            start: 0,
+            end: 1,
+            line,
+            column: 1,
+        }
+    }
+
+    fn variable(name: &str, line: usize) -> Symbol {
+        Symbol::new(
+            name,
+            SymbolKind::Variable,
+            Type::Word,
+            Location::Global(name.to_string()),
+            span(line),
+        )
+    }
+
+    #[test]
+    fn given_declared_name_lookup_finds_it() {
+        let mut table = SymbolTable::new();
+        table.declare(variable("a", 1)).unwrap();
+
+        assert_eq!(table.lookup("a"), Some(&variable("a", 1)));
+        assert_eq!(table.lookup("b"), None);
+    }
+
+    #[test]
+    fn given_duplicate_in_same_scope_return_first_declaration() {
+        let mut table = SymbolTable::new();
+        table.declare(variable("a", 1)).unwrap();
+
+        assert_eq!(table.declare(variable("a", 2)), Err(span(1)));
+    }
+
+    #[test]
+    fn given_inner_scope_shadow_until_left() {
+        let mut table = SymbolTable::new();
+        table.declare(variable("a", 1)).unwrap();
+        table.enter_scope();
+        table.declare(variable("a", 2)).unwrap();
+        table.declare(variable("b", 3)).unwrap();
+
+        assert_eq!(table.lookup("a").unwrap().span.line, 2);
+
+        let left = table.leave_scope();
+        assert_eq!(left.len(), 2);
+        assert_eq!(table.lookup("a").unwrap().span.line, 1);
+        assert_eq!(table.lookup("b"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot leave the global scope")]
+    fn given_only_global_scope_leaving_should_panic() {
+        let mut table = SymbolTable::new();
+        table.leave_scope();
+    }
+
+    #[test]
+    fn given_literal_fits_checks_signed_and_unsigned_range() {
+        assert!(Type::Byte.fits(-128));
+        assert!(Type::Byte.fits(255));
+        assert!(!Type::Byte.fits(256));
+        assert!(!Type::Word.fits(-32769));
+        assert!(Type::Word.fits(0xFFFF));
+        assert!(Type::Long.fits(0xFFFF_FFFF));
+        assert!(!Type::Long.fits(0x1_0000_0000));
+        assert!(!Type::Void.fits(0));
+        assert!(!Type::Array(Box::new(Type::Byte), 4).fits(0));
+    }
+
+    #[test]
+    fn given_record_fields_align_words_to_even_offsets() {
+        let inner = Record::new("Inner", vec![(String::from("b"), Type::Byte)]);
+        let record = Record::new(
+            "Outer",
+            vec![
+                (String::from("tag"), Type::Byte),
+                (String::from("count"), Type::Word),
+                (String::from("name"), Type::Array(Box::new(Type::Byte), 3)),
+                (String::from("total"), Type::Long),
+                (String::from("inner"), Type::Record(Box::new(inner))),
+            ],
+        );
+
+        let offsets = record
+            .fields
+            .iter()
+            .map(|f| f.offset)
+            .collect::<Vec<usize>>();
+        assert_eq!(offsets, vec![0, 2, 4, 8, 12]);
+        assert_eq!(record.size, 14);
+        assert_eq!(record.field("total").unwrap().ty, Type::Long);
+        assert_eq!(record.field("missing"), None);
+    }
+
+    #[test]
+    fn given_array_size_is_element_size_times_length() {
+        let array = Type::Array(Box::new(Type::Long), 3);
+
+        assert_eq!(array.bytes(), 12);
+        assert_eq!(array.suffix(), ".L");
+        assert_eq!(array.to_string(), "long[3]");
+    }
+
+    #[test]
+    fn given_pointer_to_record_refer_to_it_by_name() {
+        let record = Record::new("Node", vec![(String::from("value"), Type::Word)]);
+        let pointer = Type::pointer_to(Type::Record(Box::new(record)));
+
+        assert_eq!(
+            pointer,
+            Type::Pointer(Box::new(Type::Named(String::from("Node"))))
+        );
+        assert_eq!(pointer.bytes(), 4);
+        assert_eq!(pointer.to_string(), "Node*");
+        assert!(!pointer.is_scalar() && !pointer.is_aggregate());
+    }
+}
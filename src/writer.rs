@@ -1,8 +1,11 @@
 pub struct StdoutWriter {}
+
+#[cfg(test)]
 pub struct TestWriter {
     pub output: String,
 }
 
+#[cfg(test)]
 impl TestWriter {
     pub fn new() -> Self {
         Self {
@@ -12,25 +15,17 @@ impl TestWriter {
 }
 
 pub trait Writer {
-    fn write(&mut self, output: String) {}
-    fn writeln(&mut self, output: String) {}
+    fn writeln(&mut self, _output: String) {}
 }
 
 impl Writer for StdoutWriter {
-    fn write(&mut self, output: String) {
-        print!("{}", output);
-    }
-
     fn writeln(&mut self, output: String) {
         println!("{}", output);
     }
 }
 
+#[cfg(test)]
 impl Writer for TestWriter {
-    fn write(&mut self, output: String) {
-        self.output = format!("{}{}", self.output, output);
-    }
-
     fn writeln(&mut self, output: String) {
         self.output = format!("{}\n{}", self.output, output);
     }